@@ -0,0 +1,226 @@
+use std::{
+    ops::{Index, IndexMut},
+    rc::Rc,
+};
+
+/// Lines per chunk before a chunk is split in two
+const MAX_CHUNK: usize = 1024;
+
+/// Line storage for the editor, split into reference-counted chunks.
+///
+/// The first line number of every chunk is kept so a line's chunk is found by binary
+/// search. Inserting or removing a line only shifts the lines of one chunk, and cloning the
+/// buffer (as the undo history does) only copies chunk pointers. Chunks are copied
+/// lazily the first time they are modified after a clone.
+#[derive(Clone, Default)]
+pub struct LineBuffer {
+    chunks: Vec<Rc<Vec<String>>>,
+    /// Number of the first line in each chunk
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Find the chunk holding line `y` and the line's offset within it
+    fn locate(&self, y: usize) -> Option<(usize, usize)> {
+        if y >= self.len {
+            return None;
+        }
+        let c = self.starts.partition_point(|&start| start <= y) - 1;
+        Some((c, y - self.starts[c]))
+    }
+
+    pub fn get(&self, y: usize) -> Option<&String> {
+        self.locate(y).map(|(c, i)| &self.chunks[c][i])
+    }
+
+    pub fn get_mut(&mut self, y: usize) -> Option<&mut String> {
+        self.locate(y)
+            .map(|(c, i)| &mut Rc::make_mut(&mut self.chunks[c])[i])
+    }
+
+    pub fn push(&mut self, line: String) {
+        self.insert(self.len, line);
+    }
+
+    /// Insert `line` so that it becomes line `y`, shifting later lines down
+    pub fn insert(&mut self, y: usize, line: String) {
+        assert!(
+            y <= self.len,
+            "insert index {y} out of range ({})",
+            self.len
+        );
+        let (c, i) = match self.locate(y) {
+            Some(pos) => pos,
+            None if self.chunks.is_empty() => {
+                self.chunks.push(Rc::new(Vec::new()));
+                self.starts.push(0);
+                (0, 0)
+            }
+            None => {
+                let last = self.chunks.len() - 1;
+                (last, self.chunks[last].len())
+            }
+        };
+        let chunk = Rc::make_mut(&mut self.chunks[c]);
+        chunk.insert(i, line);
+        let tail = (chunk.len() > MAX_CHUNK).then(|| chunk.split_off(chunk.len() / 2));
+        for start in &mut self.starts[c + 1..] {
+            *start += 1;
+        }
+        if let Some(tail) = tail {
+            self.starts
+                .insert(c + 1, self.starts[c] + self.chunks[c].len());
+            self.chunks.insert(c + 1, Rc::new(tail));
+        }
+        self.len += 1;
+    }
+
+    /// Remove and return line `y`, shifting later lines up
+    pub fn remove(&mut self, y: usize) -> String {
+        let (c, i) = self
+            .locate(y)
+            .unwrap_or_else(|| panic!("remove index {y} out of range ({})", self.len));
+        let chunk = Rc::make_mut(&mut self.chunks[c]);
+        let line = chunk.remove(i);
+        // Chunks after this one start a line earlier
+        let next = if chunk.is_empty() {
+            self.chunks.remove(c);
+            self.starts.remove(c);
+            c
+        } else {
+            c + 1
+        };
+        for start in &mut self.starts[next..] {
+            *start -= 1;
+        }
+        self.len -= 1;
+        line
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        let line_a = std::mem::take(&mut self[a]);
+        let line_b = std::mem::replace(&mut self[b], line_a);
+        self[a] = line_b;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.chunks.iter().flat_map(|chunk| chunk.iter())
+    }
+
+    /// Iterate lines starting at line `y` without walking the lines before it
+    pub fn iter_from(&self, y: usize) -> impl Iterator<Item = &String> {
+        let (c, i) = self.locate(y).unwrap_or((self.chunks.len(), 0));
+        self.chunks
+            .iter()
+            .skip(c)
+            .enumerate()
+            .flat_map(move |(n, chunk)| chunk.iter().skip(if n == 0 { i } else { 0 }))
+    }
+
+    /// Concatenate all lines with `sep` between them
+    pub fn join(&self, sep: &str) -> String {
+        let mut text = String::new();
+        for (y, line) in self.iter().enumerate() {
+            if y > 0 {
+                text.push_str(sep);
+            }
+            text.push_str(line);
+        }
+        text
+    }
+}
+
+impl Index<usize> for LineBuffer {
+    type Output = String;
+
+    fn index(&self, y: usize) -> &String {
+        self.get(y)
+            .unwrap_or_else(|| panic!("line {y} out of range ({})", self.len))
+    }
+}
+
+impl IndexMut<usize> for LineBuffer {
+    fn index_mut(&mut self, y: usize) -> &mut String {
+        let len = self.len;
+        self.get_mut(y)
+            .unwrap_or_else(|| panic!("line {y} out of range ({len})"))
+    }
+}
+
+impl FromIterator<String> for LineBuffer {
+    fn from_iter<I: IntoIterator<Item = String>>(iter: I) -> Self {
+        let mut buffer = Self::new();
+        let mut chunk = Vec::with_capacity(MAX_CHUNK / 2);
+        for line in iter {
+            chunk.push(line);
+            if chunk.len() == MAX_CHUNK / 2 {
+                buffer.starts.push(buffer.len);
+                buffer.len += chunk.len();
+                buffer.chunks.push(Rc::new(std::mem::take(&mut chunk)));
+            }
+        }
+        if !chunk.is_empty() {
+            buffer.starts.push(buffer.len);
+            buffer.len += chunk.len();
+            buffer.chunks.push(Rc::new(chunk));
+        }
+        buffer
+    }
+}
+
+impl From<Vec<String>> for LineBuffer {
+    fn from(lines: Vec<String>) -> Self {
+        lines.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_match_a_vec_across_chunks() {
+        let mut lines: Vec<String> = (0..5000).map(|i| i.to_string()).collect();
+        let mut buffer = LineBuffer::from(lines.clone());
+        // A fixed pseudo-random walk of inserts and removes, splitting and emptying chunks
+        let mut seed = 7usize;
+        for n in 0..20_000 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let y = (seed >> 33) % (lines.len() + 1);
+            if n % 3 == 2 && y < lines.len() {
+                assert_eq!(buffer.remove(y), lines.remove(y));
+            } else {
+                buffer.insert(y, format!("new {}", n));
+                lines.insert(y, format!("new {}", n));
+            }
+        }
+        assert_eq!(buffer.len(), lines.len());
+        assert!(buffer.iter().eq(lines.iter()));
+        for y in (0..lines.len()).step_by(97) {
+            assert_eq!(buffer[y], lines[y]);
+            assert!(buffer.iter_from(y).eq(lines[y..].iter()));
+        }
+        while !lines.is_empty() {
+            assert_eq!(buffer.remove(0), lines.remove(0));
+        }
+        assert!(buffer.is_empty() && buffer.get(0).is_none());
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Tools tried in order to put text on the system clipboard
+const COPY_COMMANDS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("pbcopy", &[]),
+    ("clip.exe", &[]),
+];
+
+/// Tools tried in order to read text from the system clipboard
+const PASTE_COMMANDS: &[(&str, &[&str])] = &[
+    ("wl-paste", &["--no-newline"]),
+    ("xclip", &["-selection", "clipboard", "-o"]),
+    ("xsel", &["--clipboard", "--output"]),
+    ("pbpaste", &[]),
+    (
+        "powershell.exe",
+        &["-NoProfile", "-Command", "Get-Clipboard"],
+    ),
+];
+
+/// Clipboard shared by copy, cut and paste.
+///
+/// Text goes to the system clipboard through the platform's command-line tools
+/// (wl-clipboard, xclip, xsel, pbcopy, clip.exe). A local copy is always kept so
+/// copy and paste still work inside the editor when none of them is installed.
+#[derive(Default)]
+pub struct Clipboard {
+    local: String,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store `text` locally and on the system clipboard when possible
+    pub fn set(&mut self, text: String) {
+        copy_to_system(&text);
+        self.local = text;
+    }
+
+    /// Current clipboard contents, preferring the system clipboard
+    pub fn get(&self) -> String {
+        paste_from_system().unwrap_or_else(|| self.local.clone())
+    }
+}
+
+fn copy_to_system(text: &str) -> bool {
+    COPY_COMMANDS.iter().any(|(program, args)| {
+        let Ok(mut child) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            return false;
+        };
+        let written = child
+            .stdin
+            .take()
+            .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        child.wait().is_ok_and(|status| status.success()) && written
+    })
+}
+
+fn paste_from_system() -> Option<String> {
+    PASTE_COMMANDS.iter().find_map(|(program, args)| {
+        let output = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8(output.stdout).ok()
+    })
+}
@@ -0,0 +1,223 @@
+use rano_text_editor::editor::Editor;
+
+use crate::keymap::{self, Action};
+
+/// A command typed on the command line
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Run an action, named as in the `[keys]` section or with a vi-style abbreviation
+    Action(Action),
+    /// Move to a 1-based line and optionally a column
+    Goto(usize, Option<usize>),
+    /// Turn a buffer option on or off
+    Set(Setting, bool),
+    /// `s/query/replacement/` on the cursor line, or on every line with `%s`. The `g`
+    /// flag replaces every match on a line instead of the first.
+    Substitute {
+        query: String,
+        replacement: String,
+        whole_file: bool,
+        global: bool,
+    },
+}
+
+/// Buffer options that `:set` can change
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    Number,
+    Wrap,
+    Whitespace,
+    ReadOnly,
+    Overwrite,
+}
+
+/// Names accepted by `:set`, with `no` in front to turn the option off
+const SETTINGS: &[(&str, Setting)] = &[
+    ("number", Setting::Number),
+    ("nu", Setting::Number),
+    ("wrap", Setting::Wrap),
+    ("whitespace", Setting::Whitespace),
+    ("list", Setting::Whitespace),
+    ("readonly", Setting::ReadOnly),
+    ("ro", Setting::ReadOnly),
+    ("overwrite", Setting::Overwrite),
+];
+
+impl Setting {
+    fn name(self) -> &'static str {
+        SETTINGS
+            .iter()
+            .find(|&&(_, setting)| setting == self)
+            .map_or("", |&(name, _)| name)
+    }
+
+    /// Turn the option on or off in `editor`, noting the change in the status bar
+    pub fn apply(self, editor: &mut Editor, on: bool) {
+        match self {
+            Self::Number => editor.show_line_numbers = on,
+            Self::Wrap => {
+                editor.soft_wrap = on;
+                // Goal columns count from the line start or the row start
+                editor.goal_col = None;
+            }
+            Self::Whitespace => editor.show_whitespace = on,
+            Self::ReadOnly => editor.read_only = on,
+            Self::Overwrite => editor.overwrite = on,
+        }
+        editor.set_status(format!("{} {}", self.name(), if on { "on" } else { "off" }));
+    }
+}
+
+/// Parse a command line such as `w`, `goto 40`, `set nowrap` or `%s/foo/bar/g`.
+/// A leading `:` is optional.
+pub fn parse(input: &str) -> Result<Command, String> {
+    let input = input.trim();
+    let input = input.strip_prefix(':').unwrap_or(input).trim_start();
+    if let Some(rest) = input.strip_prefix("%s") {
+        return parse_substitute(rest, true);
+    }
+    if let Some(rest) = input.strip_prefix('s')
+        && rest
+            .chars()
+            .next()
+            .is_some_and(|c| !c.is_alphanumeric() && !c.is_whitespace())
+    {
+        return parse_substitute(rest, false);
+    }
+
+    let (name, arg) = input
+        .split_once(char::is_whitespace)
+        .map_or((input, ""), |(name, arg)| (name, arg.trim()));
+    let action = match (name, arg) {
+        ("w", "") => Action::Save,
+        ("q", "") => Action::Quit,
+        ("q!", "") => Action::ForceQuit,
+        ("wq" | "x", "") => Action::SaveQuit,
+        ("qa", "") => Action::QuitAll,
+        ("goto", position) => return parse_goto(position),
+        ("set", option) => return parse_setting(option),
+        (position, "") if position.starts_with(|c: char| c.is_ascii_digit()) => {
+            return parse_goto(position);
+        }
+        (name, "") => {
+            keymap::action_named(name).ok_or_else(|| format!("Unknown command: {}", name))?
+        }
+        (name, _) => return Err(format!("Unknown command: {}", name)),
+    };
+    Ok(Command::Action(action))
+}
+
+/// Parse `line` or `line:column`, as reported by compilers and linters. An empty column
+/// after the colon is the same as none.
+pub fn parse_position(text: &str) -> Option<(usize, Option<usize>)> {
+    let (line, column) = match text.trim().split_once(':') {
+        Some((line, "")) => (line, None),
+        Some((line, column)) => (line, Some(column.trim().parse().ok()?)),
+        None => (text.trim(), None),
+    };
+    Some((line.trim().parse().ok()?, column))
+}
+
+fn parse_goto(text: &str) -> Result<Command, String> {
+    parse_position(text)
+        .map(|(line, column)| Command::Goto(line, column))
+        .ok_or_else(|| format!("Invalid line number: {}", text))
+}
+
+fn parse_setting(option: &str) -> Result<Command, String> {
+    let (name, on) = match option.strip_prefix("no") {
+        Some(name) if SETTINGS.iter().any(|&(n, _)| n == name) => (name, false),
+        _ => (option, true),
+    };
+    SETTINGS
+        .iter()
+        .find(|&&(n, _)| n == name)
+        .map(|&(_, setting)| Command::Set(setting, on))
+        .ok_or_else(|| format!("Unknown option: {}", option))
+}
+
+/// Parse the part of `s/query/replacement/flags` after the `s`. The first character is
+/// the delimiter, which can appear in the query or replacement escaped with `\`.
+fn parse_substitute(text: &str, whole_file: bool) -> Result<Command, String> {
+    let mut chars = text.chars();
+    let delimiter = chars
+        .next()
+        .ok_or("Expected s/query/replacement/ after s")?;
+    let mut parts = vec![String::new()];
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.clone().next() == Some(delimiter) => {
+                parts.last_mut().unwrap().push(delimiter);
+                chars.next();
+            }
+            c if c == delimiter && parts.len() < 3 => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    let mut parts = parts.into_iter();
+    let query = parts.next().unwrap_or_default();
+    let replacement = parts.next().unwrap_or_default();
+    let global = match parts.next().as_deref() {
+        None | Some("") => false,
+        Some("g") => true,
+        Some(flags) => return Err(format!("Unknown substitute flags: {}", flags)),
+    };
+    if query.is_empty() {
+        return Err("Nothing to substitute".into());
+    }
+    Ok(Command::Substitute {
+        query,
+        replacement,
+        whole_file,
+        global,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_actions_and_lines() {
+        assert_eq!(parse(":w"), Ok(Command::Action(Action::Save)));
+        assert_eq!(parse("wq"), Ok(Command::Action(Action::SaveQuit)));
+        assert_eq!(parse("undo"), Ok(Command::Action(Action::Undo)));
+        assert_eq!(parse(":goto 40"), Ok(Command::Goto(40, None)));
+        assert_eq!(parse("12"), Ok(Command::Goto(12, None)));
+        assert_eq!(parse("40:12"), Ok(Command::Goto(40, Some(12))));
+        assert!(parse("goto x").is_err());
+        assert!(parse("goto 4:x").is_err());
+        assert!(parse("frobnicate").is_err());
+    }
+
+    #[test]
+    fn parses_settings() {
+        assert_eq!(parse("set number"), Ok(Command::Set(Setting::Number, true)));
+        assert_eq!(parse("set nowrap"), Ok(Command::Set(Setting::Wrap, false)));
+        assert!(parse("set bogus").is_err());
+    }
+
+    #[test]
+    fn parses_substitute() {
+        assert_eq!(
+            parse(":s/foo/bar/"),
+            Ok(Command::Substitute {
+                query: "foo".into(),
+                replacement: "bar".into(),
+                whole_file: false,
+                global: false,
+            })
+        );
+        assert_eq!(
+            parse("%s#a\\#b#c#g"),
+            Ok(Command::Substitute {
+                query: "a#b".into(),
+                replacement: "c".into(),
+                whole_file: true,
+                global: true,
+            })
+        );
+        assert!(parse("s/foo/bar/x").is_err());
+        assert!(parse("s//bar/").is_err());
+    }
+}
@@ -0,0 +1,329 @@
+use std::{env, fs, io, path::PathBuf};
+
+/// A value in the config file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    /// A quoted string
+    Str(String),
+    /// A list of quoted strings
+    List(Vec<String>),
+    /// An unquoted number or boolean
+    Bare(String),
+}
+
+impl Value {
+    /// The value as a list of strings; a single string becomes a one-element list
+    pub fn as_list(&self) -> Option<Vec<String>> {
+        match self {
+            Self::Str(s) => Some(vec![s.clone()]),
+            Self::List(items) => Some(items.clone()),
+            Self::Bare(_) => None,
+        }
+    }
+}
+
+/// One `key = value` line of the config file
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub section: String,
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+/// Settings read from `config.toml`.
+///
+/// Only the subset of TOML the editor needs is understood: `[section]` headers and
+/// `key = value` lines whose value is a quoted string, a list of quoted strings, or a
+/// bare number or boolean. Lines that don't parse are collected in `errors` and skipped.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    entries: Vec<Entry>,
+    pub errors: Vec<String>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/rano/config.toml`, falling back to `~/.config/rano/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(base.join("rano").join("config.toml"))
+    }
+
+    /// Read the config file; a missing file gives an empty config
+    pub fn load() -> Self {
+        let Some(path) = Self::default_path() else {
+            return Self::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => Self {
+                entries: Vec::new(),
+                errors: vec![format!("{}: {}", path.display(), e)],
+            },
+        }
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut config = Self::default();
+        let mut section = String::new();
+        for (i, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                match strip_comment(name).strip_suffix(']') {
+                    Some(name) if is_key(name.trim()) => section = name.trim().to_string(),
+                    _ => config
+                        .errors
+                        .push(format!("line {}: invalid section header", i + 1)),
+                }
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                config
+                    .errors
+                    .push(format!("line {}: expected `key = value`", i + 1));
+                continue;
+            };
+            let key = key.trim();
+            if !is_key(key) {
+                config
+                    .errors
+                    .push(format!("line {}: invalid key `{}`", i + 1, key));
+                continue;
+            }
+            match parse_value(value.trim()) {
+                Ok(value) => config.entries.push(Entry {
+                    section: section.clone(),
+                    key: key.to_string(),
+                    value,
+                    line: i + 1,
+                }),
+                Err(e) => config.errors.push(format!("line {}: {}", i + 1, e)),
+            }
+        }
+        config
+    }
+
+    /// Last entry for `key` in `section`
+    pub fn get(&self, section: &str, key: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.section == section && e.key == key)
+    }
+
+    /// Boolean setting `key` in `section`; a value other than `true` or `false` is
+    /// reported in `errors` and ignored
+    pub fn get_bool(&self, section: &str, key: &str, errors: &mut Vec<String>) -> Option<bool> {
+        let entry = self.get(section, key)?;
+        match &entry.value {
+            Value::Bare(v) if v == "true" => Some(true),
+            Value::Bare(v) if v == "false" => Some(false),
+            _ => {
+                errors.push(format!(
+                    "line {}: `{}` must be true or false",
+                    entry.line, key
+                ));
+                None
+            }
+        }
+    }
+
+    /// String setting `key` in `section`; other values are reported in `errors` and ignored
+    pub fn get_str(&self, section: &str, key: &str, errors: &mut Vec<String>) -> Option<String> {
+        let entry = self.get(section, key)?;
+        match &entry.value {
+            Value::Str(s) => Some(s.clone()),
+            _ => {
+                errors.push(format!("line {}: `{}` must be a string", entry.line, key));
+                None
+            }
+        }
+    }
+
+    /// Non-negative integer setting `key` in `section`; other values are reported in
+    /// `errors` and ignored
+    pub fn get_number(&self, section: &str, key: &str, errors: &mut Vec<String>) -> Option<u64> {
+        let entry = self.get(section, key)?;
+        match &entry.value {
+            Value::Bare(v) => v.parse().ok(),
+            _ => None,
+        }
+        .or_else(|| {
+            errors.push(format!("line {}: `{}` must be a number", entry.line, key));
+            None
+        })
+    }
+
+    /// Entries of `section` in file order
+    pub fn section<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Entry> {
+        self.entries.iter().filter(move |e| e.section == name)
+    }
+}
+
+fn is_key(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Drop a trailing `# comment` from text that contains no strings
+fn strip_comment(s: &str) -> &str {
+    s.split('#').next().unwrap_or("").trim()
+}
+
+/// Parse a quoted string at the start of `s`, returning it and the remaining text
+fn parse_string(s: &str) -> Result<(String, &str), String> {
+    let mut chars = s.char_indices();
+    if chars.next().map(|(_, c)| c) != Some('"') {
+        return Err("expected a quoted string".into());
+    }
+    let mut out = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((out, &s[i + 1..])),
+            '\\' => match chars.next().map(|(_, c)| c) {
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some(c @ ('"' | '\\')) => out.push(c),
+                _ => return Err("invalid escape in string".into()),
+            },
+            c => out.push(c),
+        }
+    }
+    Err("unterminated string".into())
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+    if s.starts_with('"') {
+        let (value, rest) = parse_string(s)?;
+        if !strip_comment(rest).is_empty() {
+            return Err("unexpected text after string".into());
+        }
+        return Ok(Value::Str(value));
+    }
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                if !strip_comment(after).is_empty() {
+                    return Err("unexpected text after list".into());
+                }
+                return Ok(Value::List(items));
+            }
+            let (item, after) = parse_string(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err("expected `,` or `]` in list".into());
+            }
+        }
+    }
+    let bare = strip_comment(s);
+    if bare.is_empty()
+        || !bare
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+'))
+    {
+        return Err("expected a string, list or number".into());
+    }
+    Ok(Value::Bare(bare.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_values_and_comments() {
+        let config = Config::parse(
+            "# comment\n\
+             top = 1\n\
+             [keys]\n\
+             quit = \"ctrl+x\" # trailing comment\n\
+             find_next = [\"f3\", \"ctrl+n\"]\n\
+             [editor]  # section comment\n\
+             tab_width = 4\n\
+             name = \"a \\\"b\\\" # c\\n\"\n",
+        );
+        assert!(config.errors.is_empty(), "{:?}", config.errors);
+        assert_eq!(
+            config.get("", "top").unwrap().value,
+            Value::Bare("1".into())
+        );
+        assert_eq!(
+            config.get("keys", "quit").unwrap().value,
+            Value::Str("ctrl+x".into())
+        );
+        assert_eq!(
+            config.get("keys", "find_next").unwrap().value.as_list(),
+            Some(vec!["f3".into(), "ctrl+n".into()])
+        );
+        assert_eq!(
+            config.get("editor", "name").unwrap().value,
+            Value::Str("a \"b\" # c\n".into())
+        );
+        assert!(config.get("editor", "quit").is_none());
+        assert_eq!(config.section("keys").count(), 2);
+    }
+
+    #[test]
+    fn later_entries_win() {
+        let config = Config::parse("a = 1\na = 2\n");
+        assert_eq!(config.get("", "a").unwrap().value, Value::Bare("2".into()));
+        assert_eq!(config.get("", "a").unwrap().line, 2);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let config = Config::parse(
+            "[bad section\n\
+             no equals\n\
+             bad key! = 1\n\
+             s = \"unterminated\n\
+             l = [\"a\" \"b\"]\n\
+             e = \"\\q\"\n\
+             ok = true\n",
+        );
+        assert_eq!(
+            config.errors,
+            [
+                "line 1: invalid section header",
+                "line 2: expected `key = value`",
+                "line 3: invalid key `bad key!`",
+                "line 4: unterminated string",
+                "line 5: expected `,` or `]` in list",
+                "line 6: invalid escape in string",
+            ]
+        );
+        assert!(config.get("", "ok").is_some());
+    }
+
+    #[test]
+    fn typed_getters_report_wrong_types() {
+        let config = Config::parse("flag = yes\nwidth = \"wide\"\nname = 3\nn = 40\n");
+        let mut errors = Vec::new();
+        assert_eq!(config.get_bool("", "flag", &mut errors), None);
+        assert_eq!(config.get_number("", "width", &mut errors), None);
+        assert_eq!(config.get_str("", "name", &mut errors), None);
+        assert_eq!(config.get_number("", "n", &mut errors), Some(40));
+        assert_eq!(config.get_bool("", "missing", &mut errors), None);
+        assert_eq!(
+            errors,
+            [
+                "line 1: `flag` must be true or false",
+                "line 2: `width` must be a number",
+                "line 3: `name` must be a string",
+            ]
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A calendar date and time of day
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    /// The current local time, or UTC where the local time zone is not available
+    pub fn now() -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        Self::local(secs).unwrap_or_else(|| Self::utc(secs))
+    }
+
+    /// Time `secs` seconds after the Unix epoch in UTC
+    pub fn utc(secs: i64) -> Self {
+        let days = secs.div_euclid(86_400);
+        let time = secs.rem_euclid(86_400) as u32;
+        // Civil date from a day count, after Howard Hinnant's `civil_from_days`
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        Self {
+            year: yoe + era * 400 + i64::from(month <= 2),
+            month,
+            day,
+            hour: time / 3600,
+            minute: time / 60 % 60,
+            second: time % 60,
+        }
+    }
+
+    #[cfg(unix)]
+    fn local(secs: i64) -> Option<Self> {
+        let time = secs as libc::time_t;
+        // SAFETY: `tm` is plain data that `localtime_r` fills in; both pointers are valid
+        // for the duration of the call
+        let tm = unsafe {
+            let mut tm: libc::tm = std::mem::zeroed();
+            if libc::localtime_r(&time, &mut tm).is_null() {
+                return None;
+            }
+            tm
+        };
+        Some(Self {
+            year: i64::from(tm.tm_year) + 1900,
+            month: tm.tm_mon as u32 + 1,
+            day: tm.tm_mday as u32,
+            hour: tm.tm_hour as u32,
+            minute: tm.tm_min as u32,
+            second: tm.tm_sec as u32,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn local(_secs: i64) -> Option<Self> {
+        None
+    }
+
+    /// Format like `strftime` with `%Y %m %d %H %M %S`, and `%%` for a percent sign.
+    /// Anything else is copied as is.
+    pub fn format(&self, format: &str) -> String {
+        let mut out = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => out.push_str(&format!("{:04}", self.year)),
+                Some('m') => out.push_str(&format!("{:02}", self.month)),
+                Some('d') => out.push_str(&format!("{:02}", self.day)),
+                Some('H') => out.push_str(&format!("{:02}", self.hour)),
+                Some('M') => out.push_str(&format!("{:02}", self.minute)),
+                Some('S') => out.push_str(&format!("{:02}", self.second)),
+                Some('%') => out.push('%'),
+                Some(other) => {
+                    out.push('%');
+                    out.push(other);
+                }
+                None => out.push('%'),
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i64, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    #[test]
+    fn utc_known_dates() {
+        assert_eq!(DateTime::utc(0), date(1970, 1, 1, 0, 0, 0));
+        assert_eq!(DateTime::utc(-1), date(1969, 12, 31, 23, 59, 59));
+        assert_eq!(DateTime::utc(951_782_400), date(2000, 2, 29, 0, 0, 0));
+        assert_eq!(DateTime::utc(1_709_210_096), date(2024, 2, 29, 12, 34, 56));
+        // 2100 is not a leap year
+        assert_eq!(DateTime::utc(4_107_542_400), date(2100, 3, 1, 0, 0, 0));
+        assert_eq!(DateTime::utc(-2_203_891_201), date(1900, 2, 28, 23, 59, 59));
+    }
+
+    #[test]
+    fn format_expands_fields() {
+        let time = date(2024, 2, 9, 7, 5, 3);
+        assert_eq!(time.format("%Y-%m-%d %H:%M:%S"), "2024-02-09 07:05:03");
+        assert_eq!(time.format("100%% %Y"), "100% 2024");
+        assert_eq!(time.format("%q %"), "%q %");
+        assert_eq!(time.format("plain"), "plain");
+    }
+
+    #[test]
+    fn local_time_is_within_a_day_of_utc() {
+        let secs = 1_709_210_096;
+        let Some(local) = DateTime::local(secs) else {
+            return;
+        };
+        let utc = DateTime::utc(secs);
+        // Every time zone is within 14 hours of UTC
+        assert!(matches!((local.month, local.day), (2, 29) | (3, 1)));
+        assert!(local.hour < 24 && local.minute < 60 && local.second < 60);
+        assert_eq!(local.second, utc.second);
+    }
+}
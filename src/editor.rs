@@ -0,0 +1,3124 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    buffer::LineBuffer,
+    positions, recent,
+    regex::Regex,
+    syntax::{self, Language, LineState},
+};
+
+/// Name used for the buffer when no file is given on the command line
+pub const DEFAULT_FILENAME: &str = "untitled.txt";
+
+/// Name shown for a buffer read from standard input
+const STDIN_FILENAME: &str = "[stdin]";
+
+/// Files larger than this are read incrementally instead of all at once
+const LAZY_LOAD_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Lines read ahead of the viewport when a file is loaded lazily
+pub const LOAD_AHEAD: usize = 1000;
+
+/// Bracket and quote pairs closed automatically while typing
+const DEFAULT_AUTO_PAIRS: &[(char, char)] =
+    &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+
+/// Line comment prefix for each file extension (or extensionless file name)
+const DEFAULT_COMMENT_PREFIXES: &[(&str, &str)] = &[
+    ("rs", "//"),
+    ("c", "//"),
+    ("h", "//"),
+    ("cpp", "//"),
+    ("hpp", "//"),
+    ("cs", "//"),
+    ("go", "//"),
+    ("java", "//"),
+    ("js", "//"),
+    ("ts", "//"),
+    ("swift", "//"),
+    ("kt", "//"),
+    ("py", "#"),
+    ("sh", "#"),
+    ("bash", "#"),
+    ("rb", "#"),
+    ("pl", "#"),
+    ("toml", "#"),
+    ("yaml", "#"),
+    ("yml", "#"),
+    ("makefile", "#"),
+    ("lua", "--"),
+    ("sql", "--"),
+    ("hs", "--"),
+];
+
+/// Comment prefix for file types missing from the comment prefix map
+const FALLBACK_COMMENT_PREFIX: &str = "#";
+
+/// A (line, column) position in the buffer
+pub type Position = (usize, usize);
+
+/// Bracket pairs considered when highlighting the partner of the bracket at the cursor
+const BRACKETS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
+/// Lines searched in each direction for a matching bracket
+const BRACKET_SCAN_LINES: usize = 5000;
+
+/// How long a status bar message stays visible
+const STATUS_MESSAGE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Snapshot of the buffer and cursor, stored in the undo/redo history
+#[derive(Clone)]
+struct EditSnapshot {
+    content: LineBuffer,
+    cursor_x: usize,
+    cursor_y: usize,
+    revision: u64,
+}
+
+/// Kind of mutating operation, used to group consecutive edits for undo
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EditKind {
+    Insert,
+    Newline,
+    Delete,
+    DeleteForward,
+    DeleteWord,
+    Replace,
+    Indent,
+    Line,
+    Paste,
+    Selection,
+    Comment,
+    Number,
+}
+
+impl EditKind {
+    /// Whether consecutive edits of this kind may share one undo step
+    fn coalesces(self) -> bool {
+        matches!(
+            self,
+            Self::Insert | Self::Delete | Self::DeleteForward | Self::Replace
+        )
+    }
+}
+
+/// Byte order mark some editors put at the start of UTF-8 files
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Line terminator used when writing the buffer back to disk
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    /// Pick the dominant line ending in `text`, defaulting to LF
+    fn detect(text: &str) -> Self {
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        if crlf > lf { Self::CrLf } else { Self::Lf }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+        }
+    }
+
+    /// Name shown in the status bar
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lf => "LF",
+            Self::CrLf => "CRLF",
+        }
+    }
+
+    /// Parse `lf` or `crlf`, ignoring case
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "lf" => Some(Self::Lf),
+            "crlf" => Some(Self::CrLf),
+            _ => None,
+        }
+    }
+}
+
+/// Character encoding of the file on disk
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Utf8,
+    /// ISO-8859-1, where every byte is the character with the same code
+    Latin1,
+}
+
+impl Encoding {
+    /// UTF-8 when `bytes` are valid UTF-8, otherwise Latin-1, which can decode anything
+    pub fn detect(bytes: &[u8]) -> Self {
+        if std::str::from_utf8(bytes).is_ok() {
+            Self::Utf8
+        } else {
+            Self::Latin1
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Self::Latin1 => bytes.iter().map(|&b| char::from(b)).collect(),
+        }
+    }
+
+    /// `text` as bytes in this encoding; characters it has no code for are an error
+    pub fn encode(self, text: &str) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Utf8 => Ok(text.as_bytes().to_vec()),
+            Self::Latin1 => text
+                .chars()
+                .map(|c| {
+                    u8::try_from(c).map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("{:?} can't be written as {}", c, self.name()),
+                        )
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Name shown in the status bar
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Utf8 => "UTF-8",
+            Self::Latin1 => "Latin-1",
+        }
+    }
+}
+
+/// How a line differs from the file as it was opened or last saved
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineChange {
+    Unchanged,
+    Added,
+    Modified,
+}
+
+/// Most cells in the table `line_changes` compares a changed region with; bigger regions
+/// are marked modified throughout
+const MAX_DIFF_CELLS: usize = 1 << 22;
+
+/// What was on disk for the buffer's file when it was opened or last saved
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileStatus {
+    /// There is no file yet; saving creates it
+    New,
+    /// The file exists but holds nothing at all
+    Empty,
+    /// The file has content, if only a line break
+    Existing,
+}
+
+/// Remaining lines of a large file that have not been read into the buffer yet
+struct LazyLines {
+    reader: BufReader<File>,
+    encoding: Encoding,
+    crlf: usize,
+    lf: usize,
+    last_had_newline: bool,
+}
+
+impl LazyLines {
+    /// Read the next line without its terminator, or `None` at end of file
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        let mut bytes = Vec::new();
+        if self.reader.read_until(b'\n', &mut bytes)? == 0 {
+            return Ok(None);
+        }
+        let mut line = self.encoding.decode(&bytes);
+        self.last_had_newline = line.ends_with('\n');
+        if self.last_had_newline {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+                self.crlf += 1;
+            } else {
+                self.lf += 1;
+            }
+        }
+        Ok(Some(line))
+    }
+}
+
+/// Document statistics shown by the Ctrl+T popup
+pub struct DocumentStats {
+    pub lines: usize,
+    pub words: usize,
+    pub chars: usize,
+    pub chars_no_whitespace: usize,
+}
+
+/// Cursor movements understood by `Editor::move_cursor`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Motion {
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+}
+
+/// A search hit, in line and character coordinates
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub y: usize,
+    pub x: usize,
+    pub len: usize,
+}
+
+/// Represents the current state of the text editor
+pub struct Editor {
+    pub filename: String,
+    /// Lines of the buffer. Once loaded there is always at least one; an empty file is a
+    /// single empty line, so the cursor can reach every line from 0 to `len() - 1`.
+    pub content: LineBuffer,
+    /// Lines as they were opened or last saved, to show changes against. Cloning the
+    /// buffer shares its chunks, so this costs little until lines are edited.
+    original: LineBuffer,
+    /// `line_changes` as last computed, until the buffer or `original` changes
+    changes: RefCell<Option<Rc<[LineChange]>>>,
+    pending: Option<LazyLines>,
+    pub line_ending: LineEnding,
+    /// Encoding the file is read and written in
+    pub encoding: Encoding,
+    /// Whether the file starts with a UTF-8 byte order mark, which is kept out of the
+    /// buffer and written back on save
+    pub bom: bool,
+    final_newline: bool,
+    pub file_status: FileStatus,
+    /// Modification time of the file when it was opened or last saved
+    disk_mtime: Option<SystemTime>,
+    pub cursor_x: usize,
+    pub cursor_y: usize,
+    pub scroll_y: usize,
+    pub scroll_x: usize,
+    selection_start: Option<(usize, usize)>,
+    pub modified: bool,
+    pub read_only: bool,
+    /// Typed characters replace the one under the cursor instead of being inserted
+    pub overwrite: bool,
+    /// Remove trailing spaces and tabs from every line when saving
+    pub strip_trailing: bool,
+    /// Draw trailing spaces and tabs on a colored background
+    pub highlight_trailing: bool,
+    /// Recovery copy of unsaved changes, `.filename.swp` next to the file; `None` for
+    /// buffers read from stdin
+    swap_path: Option<String>,
+    /// The buffer changed since the swap file was last written
+    swap_dirty: bool,
+    /// Record the cursor position on save and close so reopening the file returns to it
+    pub remember_position: bool,
+    /// Named positions set with Ctrl+B
+    pub marks: HashMap<char, Position>,
+    /// First line touched by the current command's edits and the line count before them,
+    /// used to move marks below inserted or removed lines
+    edit_origin: Option<(usize, usize)>,
+    search_query: Option<String>,
+    pub case_sensitive: bool,
+    /// Interpret search queries as regular expressions
+    pub regex_search: bool,
+    /// Compiled search query when `regex_search` is on
+    search_regex: Option<Regex>,
+    pub current_match: Option<SearchMatch>,
+    /// 1-based index of `current_match` among all matches, and the number of matches
+    pub match_position: (usize, usize),
+    undo_stack: Vec<EditSnapshot>,
+    redo_stack: Vec<EditSnapshot>,
+    /// Identifies the buffer contents; every edit gets a new revision, and undo and redo
+    /// go back to the one the snapshot was taken at
+    revision: u64,
+    /// Highest revision handed out so far
+    last_revision: u64,
+    /// Revision last written to disk, or `None` when no revision in the history matches
+    /// the file
+    saved_revision: Option<u64>,
+    last_edit: Option<EditKind>,
+    coalesce_edits: bool,
+    /// Column that Up/Down aim for, with the cursor position the last vertical move left.
+    /// It only applies while the cursor is still there, so any other movement resets it.
+    pub goal_col: Option<(Position, usize)>,
+    pub tab_width: usize,
+    /// Indent with `tab_width` spaces instead of a tab character
+    pub use_spaces: bool,
+    pub show_line_numbers: bool,
+    pub highlight_current_line: bool,
+    /// Draw spaces and tabs as faint `·` and `→`
+    pub show_whitespace: bool,
+    /// Wrap lines wider than the view onto continuation rows instead of scrolling sideways
+    pub soft_wrap: bool,
+    pub syntax_highlighting: bool,
+    /// Pairs whose closing character is inserted along with the opening one
+    pub auto_pairs: Vec<(char, char)>,
+    /// Line comment prefix by file extension, checked in order
+    pub comment_prefixes: Vec<(String, String)>,
+    /// Tokenizer state at the start of each line, computed from the top as far as needed
+    pub syntax_states: Vec<LineState>,
+    pub status_message: Option<(String, Instant)>,
+}
+
+impl Editor {
+    /// Load file or start with an empty buffer if it doesn't exist.
+    /// Directories and files that can't be read are errors.
+    pub fn open(filename: String) -> io::Result<Self> {
+        let mtime = modified_time(&filename);
+        let size = fs::metadata(&filename).map_or(0, |m| m.len());
+        if size > LAZY_LOAD_THRESHOLD
+            && let Ok(file) = File::open(&filename)
+        {
+            let mut editor = Self::open_lazy(filename, file);
+            editor.disk_mtime = mtime;
+            return Ok(editor);
+        }
+
+        let bytes = match fs::read(&filename) {
+            Ok(bytes) => bytes,
+            // A new file starts out empty and is created on save
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut editor = Self::from_text(filename, "");
+                editor.file_status = FileStatus::New;
+                return Ok(editor);
+            }
+            Err(e) if fs::metadata(&filename).is_ok_and(|m| m.is_dir()) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("{} is a directory", filename),
+                ));
+            }
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("cannot open {}: {}", filename, e),
+                ));
+            }
+        };
+        let (bom, bytes) = match bytes.strip_prefix(UTF8_BOM) {
+            Some(rest) => (true, rest),
+            None => (false, &bytes[..]),
+        };
+        let encoding = Encoding::detect(bytes);
+        let mut editor = Self::from_text(filename, &encoding.decode(bytes));
+        editor.encoding = encoding;
+        editor.bom = bom;
+        editor.disk_mtime = mtime;
+        Ok(editor)
+    }
+
+    /// Buffer holding `text`, with the line ending and final newline taken from it
+    fn from_text(filename: String, text: &str) -> Self {
+        let line_ending = LineEnding::detect(text);
+        let final_newline = text.is_empty() || text.ends_with('\n');
+        let mut content = text.lines().map(|l| l.to_string()).collect::<LineBuffer>();
+        if content.is_empty() {
+            content.push(String::new());
+        }
+        let mut editor = Self::with_content(filename, content, line_ending, final_newline);
+        if text.is_empty() {
+            editor.file_status = FileStatus::Empty;
+        }
+        editor
+    }
+
+    /// Unnamed buffer holding `lines`, without reading or writing any file. No lines at
+    /// all gives a single empty line, like an empty file.
+    pub fn from_lines(lines: Vec<String>) -> Self {
+        let mut content = LineBuffer::from(lines);
+        if content.is_empty() {
+            content.push(String::new());
+        }
+        let mut editor =
+            Self::with_content(DEFAULT_FILENAME.to_string(), content, LineEnding::Lf, true);
+        editor.swap_path = None;
+        editor
+    }
+
+    /// Buffer holding text piped in on stdin. It has no file, so it starts out unsaved
+    /// and needs a name to be saved.
+    pub fn from_stdin(text: &str) -> Self {
+        let mut editor = Self::from_text(STDIN_FILENAME.to_string(), text);
+        editor.swap_path = None;
+        editor.modified = !text.is_empty();
+        if editor.modified {
+            editor.saved_revision = None;
+        }
+        editor
+    }
+
+    /// Whether the buffer holds nothing but a single empty line
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_none() && self.content.len() == 1 && self.content[0].is_empty()
+    }
+
+    /// Whether saving has to ask for a filename first
+    pub fn needs_name(&self) -> bool {
+        self.filename == DEFAULT_FILENAME || self.filename == STDIN_FILENAME
+    }
+
+    /// Replace the buffer with the file's current contents on disk.
+    /// The scroll position is kept and the cursor moves to the top of the view.
+    pub fn reload(&mut self) -> io::Result<()> {
+        let fresh = Self::open(self.filename.clone())?;
+        self.content = fresh.content;
+        self.original = fresh.original;
+        self.forget_line_changes();
+        self.pending = fresh.pending;
+        self.line_ending = fresh.line_ending;
+        self.encoding = fresh.encoding;
+        self.bom = fresh.bom;
+        self.final_newline = fresh.final_newline;
+        self.file_status = fresh.file_status;
+        self.disk_mtime = fresh.disk_mtime;
+        self.ensure_loaded(self.scroll_y + LOAD_AHEAD);
+        self.scroll_y = self.scroll_y.min(self.content.len().saturating_sub(1));
+        self.cursor_y = self.scroll_y;
+        self.cursor_x = 0;
+        self.scroll_x = 0;
+        self.selection_start = None;
+        self.current_match = None;
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.saved_revision = Some(self.revision);
+        self.last_edit = None;
+        self.syntax_states.clear();
+        self.modified = false;
+        self.remove_swap();
+        self.set_status("Reloaded");
+        Ok(())
+    }
+
+    /// Whether the file was modified by someone else since it was opened or last saved
+    pub fn changed_on_disk(&self) -> bool {
+        match (self.disk_mtime, modified_time(&self.filename)) {
+            (Some(recorded), Some(current)) => current > recorded,
+            (None, Some(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Save a modified buffer after the user has been idle. The unnamed buffer and files
+    /// changed on disk are left for an explicit save.
+    pub fn autosave(&mut self) {
+        if !self.modified || self.read_only || self.needs_name() || self.changed_on_disk() {
+            return;
+        }
+        match self.save(None) {
+            Ok(()) => self.set_status("Autosaved"),
+            Err(e) => self.set_status(format!("Autosave failed: {}", e)),
+        }
+    }
+
+    /// Start with an empty buffer and read lines from `file` only as they are needed.
+    /// The line ending is guessed from the first lines read; the rest of the file is
+    /// loaded in full before the first edit, save or search.
+    fn open_lazy(filename: String, file: File) -> Self {
+        let mut editor = Self::with_content(filename, LineBuffer::new(), LineEnding::Lf, true);
+        let mut reader = BufReader::new(file);
+        if reader.fill_buf().is_ok_and(|buf| buf.starts_with(UTF8_BOM)) {
+            reader.consume(UTF8_BOM.len());
+            editor.bom = true;
+        }
+        // The encoding is guessed from the start of the file; a character cut off at the
+        // end of the buffer doesn't make it invalid UTF-8
+        editor.encoding = match reader.fill_buf().map(std::str::from_utf8) {
+            Ok(Err(e)) if e.error_len().is_some() => Encoding::Latin1,
+            _ => Encoding::Utf8,
+        };
+        editor.pending = Some(LazyLines {
+            reader,
+            encoding: editor.encoding,
+            crlf: 0,
+            lf: 0,
+            last_had_newline: true,
+        });
+        editor.ensure_loaded(LOAD_AHEAD);
+        if let Some(lines) = &editor.pending {
+            editor.line_ending = if lines.crlf > lines.lf {
+                LineEnding::CrLf
+            } else {
+                LineEnding::Lf
+            };
+        }
+        editor
+    }
+
+    fn with_content(
+        filename: String,
+        content: LineBuffer,
+        line_ending: LineEnding,
+        final_newline: bool,
+    ) -> Self {
+        Self {
+            swap_path: Some(swap_path_for(&filename)),
+            filename,
+            original: content.clone(),
+            changes: RefCell::new(None),
+            content,
+            pending: None,
+            line_ending,
+            encoding: Encoding::Utf8,
+            bom: false,
+            final_newline,
+            file_status: FileStatus::Existing,
+            disk_mtime: None,
+            cursor_x: 0,
+            cursor_y: 0,
+            scroll_y: 0,
+            scroll_x: 0,
+            selection_start: None,
+            modified: false,
+            read_only: false,
+            overwrite: false,
+            strip_trailing: false,
+            highlight_trailing: true,
+            swap_dirty: false,
+            remember_position: false,
+            marks: HashMap::new(),
+            edit_origin: None,
+            search_query: None,
+            case_sensitive: true,
+            regex_search: false,
+            search_regex: None,
+            current_match: None,
+            match_position: (0, 0),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            revision: 0,
+            last_revision: 0,
+            saved_revision: Some(0),
+            last_edit: None,
+            coalesce_edits: true,
+            goal_col: None,
+            tab_width: 4,
+            use_spaces: true,
+            show_line_numbers: true,
+            highlight_current_line: true,
+            show_whitespace: false,
+            soft_wrap: false,
+            syntax_highlighting: true,
+            auto_pairs: DEFAULT_AUTO_PAIRS.to_vec(),
+            comment_prefixes: DEFAULT_COMMENT_PREFIXES
+                .iter()
+                .map(|&(ext, prefix)| (ext.to_string(), prefix.to_string()))
+                .collect(),
+            syntax_states: Vec::new(),
+            status_message: None,
+        }
+    }
+
+    /// Language used for highlighting, if any
+    pub fn language(&self) -> Option<Language> {
+        if !self.syntax_highlighting {
+            return None;
+        }
+        Language::from_filename(&self.filename)
+    }
+
+    /// Compute tokenizer states for lines up to index `upto`
+    pub fn update_syntax(&mut self, upto: usize) {
+        let Some(lang) = self.language() else {
+            return;
+        };
+        let upto = upto.min(self.content.len().saturating_sub(1));
+        if self.syntax_states.is_empty() {
+            self.syntax_states.push(LineState::Normal);
+        }
+        while self.syntax_states.len() <= upto {
+            let y = self.syntax_states.len() - 1;
+            let chars: Vec<char> = self.content[y].chars().collect();
+            let (_, next) = syntax::highlight_line(lang, &chars, self.syntax_states[y]);
+            self.syntax_states.push(next);
+        }
+    }
+
+    /// Forget cached tokenizer states after line `y` changed
+    fn invalidate_syntax(&mut self, y: usize) {
+        self.syntax_states.truncate(y + 1);
+    }
+
+    /// Make sure lines up to index `upto` are loaded when the file is read lazily
+    pub fn ensure_loaded(&mut self, upto: usize) {
+        let Some(lines) = self.pending.as_mut() else {
+            return;
+        };
+        while self.content.len() <= upto {
+            match lines.next_line() {
+                Ok(Some(line)) => self.content.push(line),
+                Ok(None) => {
+                    self.original = self.content.clone();
+                    self.final_newline = self.content.is_empty() || lines.last_had_newline;
+                    if lines.crlf + lines.lf > 0 {
+                        self.line_ending = if lines.crlf > lines.lf {
+                            LineEnding::CrLf
+                        } else {
+                            LineEnding::Lf
+                        };
+                    }
+                    self.pending = None;
+                    return;
+                }
+                Err(e) => {
+                    // Saving a partially read file would truncate it on disk
+                    self.read_only = true;
+                    self.original = self.content.clone();
+                    self.set_status(format!("Error reading file: {} (opened read-only)", e));
+                    self.pending = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Read the rest of a lazily loaded file into the buffer
+    pub fn load_all(&mut self) {
+        self.ensure_loaded(usize::MAX);
+    }
+
+    /// Save file to disk (optionally under a new name)
+    pub fn save(&mut self, new_name: Option<String>) -> io::Result<()> {
+        // Reading the rest of the file can fail and make the buffer read-only
+        self.load_all();
+        if !self.can_edit() {
+            return Ok(());
+        }
+        if self.strip_trailing {
+            self.strip_trailing_whitespace();
+        }
+        let target = new_name.unwrap_or_else(|| self.filename.clone());
+        let bytes = self.file_bytes()?;
+        let mut file = File::create(&target)?;
+        file.write_all(&bytes)?;
+        self.file_status = if bytes.is_empty() {
+            FileStatus::Empty
+        } else {
+            FileStatus::Existing
+        };
+        self.disk_mtime = modified_time(&target);
+        self.original = self.content.clone();
+        self.forget_line_changes();
+        self.remove_swap();
+        if target != self.filename {
+            self.syntax_states.clear();
+            self.swap_path = Some(swap_path_for(&target));
+        }
+        self.filename = target;
+        self.modified = false;
+        self.saved_revision = Some(self.revision);
+        // Typing on after saving starts a new undo step, so undo can stop at the saved text
+        self.last_edit = None;
+        self.store_position();
+        let _ = recent::add(&self.filename);
+        self.set_status("Saved");
+        Ok(())
+    }
+
+    /// The buffer as it is written to disk, in its encoding and with its byte order mark
+    pub fn file_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = if self.bom && self.encoding == Encoding::Utf8 {
+            UTF8_BOM.to_vec()
+        } else {
+            Vec::new()
+        };
+        bytes.extend(self.encoding.encode(&self.text())?);
+        Ok(bytes)
+    }
+
+    /// Write the buffer with `ending` from now on; the change counts as a modification
+    pub fn set_line_ending(&mut self, ending: LineEnding) {
+        if ending == self.line_ending || !self.can_edit() {
+            return;
+        }
+        self.line_ending = ending;
+        // Undo doesn't bring the old line ending back, so no earlier state is the file
+        self.saved_revision = None;
+        self.modified = true;
+        self.swap_dirty = true;
+        self.set_status(format!("Line endings: {}", ending.name()));
+    }
+
+    /// Set mark `name` at the cursor
+    pub fn set_mark(&mut self, name: char) {
+        self.marks.insert(name, (self.cursor_y, self.cursor_x));
+        self.set_status(format!("Mark {} set", name));
+    }
+
+    /// Move the cursor to mark `name`, clamped in case its text was deleted
+    pub fn goto_mark(&mut self, name: char, visible_height: usize) {
+        let Some(&(y, x)) = self.marks.get(&name) else {
+            self.set_status(format!("Mark {} not set", name));
+            return;
+        };
+        self.ensure_loaded(y + LOAD_AHEAD);
+        self.cursor_y = y.min(self.content.len().saturating_sub(1));
+        self.cursor_x = x.min(self.line_len(self.cursor_y));
+        self.last_edit = None;
+        self.center_on_cursor(visible_height);
+    }
+
+    /// Shift marks below the lines changed by the last command so they stay on the same
+    /// text; marks on removed lines move to the first changed line
+    pub fn adjust_marks(&mut self) {
+        let Some((first, old_len)) = self.edit_origin.take() else {
+            return;
+        };
+        let new_len = self.content.len();
+        for (y, _) in self.marks.values_mut().filter(|(y, _)| *y > first) {
+            if new_len >= old_len {
+                *y += new_len - old_len;
+            } else {
+                let removed = old_len - new_len;
+                *y = if *y > first + removed {
+                    *y - removed
+                } else {
+                    first
+                };
+            }
+        }
+    }
+
+    /// Remember the cursor position for the next time the file is opened
+    pub fn store_position(&self) {
+        if self.remember_position {
+            // Losing the position is harmless, so failures are ignored
+            let _ = positions::store(&self.filename, (self.cursor_y, self.cursor_x));
+        }
+    }
+
+    /// Move the cursor to a remembered position, clamped to the current buffer
+    pub fn restore_position(&mut self, (y, x): Position, visible_height: usize) {
+        self.ensure_loaded(y + LOAD_AHEAD);
+        self.cursor_y = y.min(self.content.len().saturating_sub(1));
+        self.cursor_x = x.min(self.line_len(self.cursor_y));
+        self.center_on_cursor(visible_height);
+    }
+
+    /// Buffer contents as written to disk. An empty buffer for a new or empty file stays
+    /// an empty file; for a file that had content it is a single line break.
+    pub fn text(&self) -> String {
+        let ending = self.line_ending.as_str();
+        let mut text = self.content.join(ending);
+        if self.final_newline && !(self.is_empty() && self.file_status != FileStatus::Existing) {
+            text.push_str(ending);
+        }
+        text
+    }
+
+    /// Write unsaved changes to the swap file if they changed since the last write
+    pub fn write_swap(&mut self) {
+        if !self.swap_dirty {
+            return;
+        }
+        self.swap_dirty = false;
+        let Some(path) = &self.swap_path else {
+            return;
+        };
+        if !self.modified {
+            self.remove_swap();
+        } else if let Err(e) = fs::write(path, self.text()) {
+            self.set_status(format!("Error writing swap file: {}", e));
+        }
+    }
+
+    pub fn remove_swap(&mut self) {
+        if let Some(path) = &self.swap_path {
+            let _ = fs::remove_file(path);
+        }
+        self.swap_dirty = false;
+    }
+
+    /// Whether a swap file newer than the file on disk was left behind
+    pub fn has_swap(&self) -> bool {
+        let Some(path) = &self.swap_path else {
+            return false;
+        };
+        match (modified_time(path), self.disk_mtime) {
+            (Some(swap), Some(file)) => swap > file,
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
+
+    /// Replace the buffer with the contents of the swap file, leaving it unsaved
+    pub fn recover_swap(&mut self) -> io::Result<()> {
+        let Some(path) = self.swap_path.clone() else {
+            return Ok(());
+        };
+        let mut swap = Self::open(path)?;
+        swap.load_all();
+        // The rest of the file is still needed to show changes against
+        self.load_all();
+        self.content = swap.content;
+        self.forget_line_changes();
+        self.line_ending = swap.line_ending;
+        self.final_newline = swap.final_newline;
+        self.cursor_y = 0;
+        self.cursor_x = 0;
+        self.syntax_states.clear();
+        self.saved_revision = None;
+        self.modified = true;
+        self.set_status("Recovered unsaved changes from swap file");
+        Ok(())
+    }
+
+    /// Save, reporting any failure in the status bar instead of returning it.
+    /// Returns whether the buffer was written.
+    pub fn try_save(&mut self, new_name: Option<String>) -> bool {
+        match self.save(new_name) {
+            Ok(()) => !self.modified,
+            Err(e) => {
+                self.set_status(format!("Error saving: {}", e));
+                false
+            }
+        }
+    }
+
+    /// Remove trailing spaces and tabs from every line, keeping the cursor within its line
+    fn strip_trailing_whitespace(&mut self) {
+        let dirty: Vec<usize> = self
+            .content
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| trim_trailing_whitespace(line).len() < line.len())
+            .map(|(y, _)| y)
+            .collect();
+        for &y in &dirty {
+            let line = &mut self.content[y];
+            line.truncate(trim_trailing_whitespace(line).len());
+        }
+        if let Some(&first) = dirty.first() {
+            self.invalidate_syntax(first);
+        }
+        self.cursor_x = self.cursor_x.min(self.line_len(self.cursor_y));
+    }
+
+    /// Show a short-lived message in the status bar
+    pub fn set_status(&mut self, message: impl Into<String>) {
+        self.status_message = Some((message.into(), Instant::now()));
+    }
+
+    /// Current status bar message, if it hasn't expired yet
+    pub fn status_message(&self) -> Option<&str> {
+        self.status_message
+            .as_ref()
+            .filter(|(_, at)| at.elapsed() < STATUS_MESSAGE_TIMEOUT)
+            .map(|(msg, _)| msg.as_str())
+    }
+
+    fn snapshot(&self) -> EditSnapshot {
+        EditSnapshot {
+            content: self.content.clone(),
+            cursor_x: self.cursor_x,
+            cursor_y: self.cursor_y,
+            revision: self.revision,
+        }
+    }
+
+    fn restore(&mut self, snapshot: EditSnapshot) {
+        self.content = snapshot.content;
+        self.cursor_x = snapshot.cursor_x;
+        self.cursor_y = snapshot.cursor_y;
+        self.revision = snapshot.revision;
+        self.syntax_states.clear();
+        self.forget_line_changes();
+        self.modified = self.saved_revision != Some(self.revision);
+        self.swap_dirty = true;
+        self.last_edit = None;
+    }
+
+    /// Check that the buffer may be edited, flashing a message when it is read-only
+    pub fn can_edit(&mut self) -> bool {
+        if self.read_only {
+            self.set_status("Buffer is read-only");
+        }
+        !self.read_only
+    }
+
+    /// Prepare for a mutating operation, returning `false` if the buffer is read-only.
+    /// Saves the current state for undo; consecutive edits of the same kind share one
+    /// undo step when coalescing is on.
+    fn begin_edit(&mut self, kind: EditKind) -> bool {
+        self.load_all();
+        if !self.can_edit() {
+            return false;
+        }
+        if self.edit_origin.is_none() {
+            let first = self.selection().map_or(self.cursor_y, |((y, _), _)| y);
+            // Backspace at the start of a line joins it onto the line above
+            let joins_up =
+                kind == EditKind::Delete && self.selection().is_none() && self.cursor_x == 0;
+            let first = if joins_up {
+                first.saturating_sub(1)
+            } else {
+                first
+            };
+            self.edit_origin = Some((first, self.content.len()));
+        }
+        let coalesce = self.coalesce_edits && kind.coalesces();
+        if !(coalesce && self.last_edit == Some(kind)) {
+            self.undo_stack.push(self.snapshot());
+        }
+        self.last_revision += 1;
+        self.revision = self.last_revision;
+        self.redo_stack.clear();
+        self.last_edit = Some(kind);
+        self.swap_dirty = true;
+        self.forget_line_changes();
+        // Line moves also touch the line above the cursor
+        let first = self.selection().map_or(self.cursor_y, |((y, _), _)| y);
+        self.invalidate_syntax(first.min(self.cursor_y).saturating_sub(1));
+        self.current_match = None;
+        self.selection_start = None;
+        true
+    }
+
+    /// Make the next edit start a new undo step even if it could share the last one
+    pub fn end_undo_step(&mut self) {
+        self.last_edit = None;
+    }
+
+    /// Whether part of a large file is still to be read from disk
+    pub fn is_partially_loaded(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Fold the undo step just started into the one before it, so that replacing a
+    /// selection undoes together with the deletion of the selected text
+    fn join_previous_undo(&mut self) {
+        if self.undo_stack.len() > 1 {
+            self.undo_stack.pop();
+        }
+    }
+
+    /// Revert the last edit (Ctrl+Z)
+    pub fn undo(&mut self) {
+        if !self.can_edit() {
+            return;
+        }
+        if let Some(snapshot) = self.undo_stack.pop() {
+            self.redo_stack.push(self.snapshot());
+            self.restore(snapshot);
+        }
+    }
+
+    /// Re-apply the last undone edit (Ctrl+Y)
+    pub fn redo(&mut self) {
+        if !self.can_edit() {
+            return;
+        }
+        if let Some(snapshot) = self.redo_stack.pop() {
+            self.undo_stack.push(self.snapshot());
+            self.restore(snapshot);
+        }
+    }
+
+    /// Insert a character at the current cursor position
+    pub fn insert_char(&mut self, ch: char) {
+        let replaced_selection = self.delete_selection();
+        // Typing a closing character in front of the same one steps over it
+        if !replaced_selection
+            && !self.read_only
+            && self.auto_pairs.iter().any(|&(_, close)| close == ch)
+            && self.char_at(self.cursor_y, self.cursor_x) == Some(ch)
+        {
+            self.cursor_x += 1;
+            return;
+        }
+        if !self.begin_edit(EditKind::Insert) {
+            return;
+        }
+        if replaced_selection {
+            self.join_previous_undo();
+        }
+        if self.cursor_y >= self.content.len() {
+            self.content.push(String::new());
+        }
+        let overwrite = self.overwrite && self.cursor_x < self.line_len(self.cursor_y);
+        let closing = if overwrite { None } else { self.auto_close(ch) };
+        let line = &mut self.content[self.cursor_y];
+        let idx = byte_index(line, self.cursor_x);
+        if overwrite {
+            line.remove(idx);
+        }
+        line.insert(idx, ch);
+        if let Some(close) = closing {
+            line.insert(idx + ch.len_utf8(), close);
+        }
+        self.cursor_x += 1;
+        self.modified = true;
+    }
+
+    /// Closing character to insert after typing `ch` at the cursor, if any.
+    /// Quotes are not closed inside a string or right after a word character.
+    fn auto_close(&self, ch: char) -> Option<char> {
+        let &(open, close) = self.auto_pairs.iter().find(|&&(open, _)| open == ch)?;
+        if open == close {
+            let before: Vec<char> = self
+                .current_line()
+                .map_or_else(Vec::new, |l| l.chars().take(self.cursor_x).collect());
+            let in_string = before.iter().filter(|&&c| c == open).count() % 2 == 1;
+            if in_string || before.last().is_some_and(|&c| is_word_char(c)) {
+                return None;
+            }
+        }
+        Some(close)
+    }
+
+    /// Bracket at or just before the cursor, with the position of its partner if it has one
+    pub fn bracket_match(&self) -> Option<(Position, Option<Position>)> {
+        let y = self.cursor_y;
+        let (x, ch) = [Some(self.cursor_x), self.cursor_x.checked_sub(1)]
+            .into_iter()
+            .flatten()
+            .find_map(|x| {
+                let c = self.char_at(y, x)?;
+                BRACKETS
+                    .iter()
+                    .any(|&(open, close)| c == open || c == close)
+                    .then_some((x, c))
+            })?;
+        Some(((y, x), self.find_partner(y, x, ch)))
+    }
+
+    /// Move the cursor onto the partner of the bracket at or just before it (Ctrl+])
+    pub fn jump_to_bracket(&mut self, visible_height: usize) {
+        match self.bracket_match() {
+            Some((_, Some((y, x)))) => {
+                self.cursor_y = y;
+                self.cursor_x = x;
+                self.last_edit = None;
+                self.center_on_cursor(visible_height);
+            }
+            Some((_, None)) => self.set_status("No matching bracket"),
+            None => {}
+        }
+    }
+
+    /// Scan from the bracket `ch` at (`y`, `x`) for its partner, respecting nesting
+    fn find_partner(&self, y: usize, x: usize, ch: char) -> Option<Position> {
+        let mut depth = 0usize;
+        if let Some(&(open, close)) = BRACKETS.iter().find(|&&(open, _)| open == ch) {
+            let lines = self.content.iter_from(y).take(BRACKET_SCAN_LINES);
+            for (dy, line) in lines.enumerate() {
+                let skip = if dy == 0 { x } else { 0 };
+                for (cx, c) in line.chars().enumerate().skip(skip) {
+                    if c == open {
+                        depth += 1;
+                    } else if c == close {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some((y + dy, cx));
+                        }
+                    }
+                }
+            }
+            return None;
+        }
+        let &(open, close) = BRACKETS.iter().find(|&&(_, close)| close == ch)?;
+        for cy in (y.saturating_sub(BRACKET_SCAN_LINES)..=y).rev() {
+            let chars: Vec<char> = self.content.get(cy)?.chars().collect();
+            let end = if cy == y { x + 1 } else { chars.len() };
+            for cx in (0..end).rev() {
+                if chars[cx] == close {
+                    depth += 1;
+                } else if chars[cx] == open {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((cy, cx));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Character at column `x` of line `y`, if any
+    fn char_at(&self, y: usize, x: usize) -> Option<char> {
+        self.content.get(y)?.chars().nth(x)
+    }
+
+    /// Handle line breaks (Enter key), carrying the current indentation onto the new line.
+    /// A selection is replaced by the line break.
+    pub fn insert_newline(&mut self) {
+        let replaced_selection = self.delete_selection();
+        if !self.begin_edit(EditKind::Newline) {
+            return;
+        }
+        if replaced_selection {
+            self.join_previous_undo();
+        }
+        let mut indent_len = 0;
+        if self.cursor_y >= self.content.len() {
+            self.content.push(String::new());
+        } else {
+            let line = &mut self.content[self.cursor_y];
+            let rest = line.split_off(byte_index(line, self.cursor_x));
+            let indent: String = line
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .collect();
+            indent_len = indent.chars().count();
+            self.content.insert(self.cursor_y + 1, indent + &rest);
+        }
+        self.cursor_y += 1;
+        self.cursor_x = indent_len;
+        self.modified = true;
+    }
+
+    /// Insert `text` at the cursor as a single undo step, splitting it into lines.
+    /// A selection is replaced by the text.
+    pub fn insert_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let replaced_selection = self.delete_selection();
+        if !self.begin_edit(EditKind::Paste) {
+            return;
+        }
+        if replaced_selection {
+            self.join_previous_undo();
+        }
+        while self.cursor_y >= self.content.len() {
+            self.content.push(String::new());
+        }
+        let line = &mut self.content[self.cursor_y];
+        let rest = line.split_off(byte_index(line, self.cursor_x));
+        let mut pieces = text.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l));
+        let first = pieces.next().unwrap_or_default();
+        line.push_str(first);
+        let mut x = self.cursor_x + first.chars().count();
+        for piece in pieces {
+            self.cursor_y += 1;
+            self.content.insert(self.cursor_y, piece.to_string());
+            x = piece.chars().count();
+        }
+        self.content[self.cursor_y].push_str(&rest);
+        self.cursor_x = x;
+        self.modified = true;
+    }
+
+    /// Text copied by Ctrl+C: the selection, or else the current line with its line break
+    pub fn copy_text(&self) -> String {
+        self.selected_text()
+            .unwrap_or_else(|| self.current_line().cloned().unwrap_or_default() + "\n")
+    }
+
+    /// Anchor a selection at the cursor unless one is already in progress
+    pub fn extend_selection(&mut self) {
+        if self.selection_start.is_none() {
+            self.selection_start = Some((self.cursor_y, self.cursor_x));
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection_start = None;
+    }
+
+    /// The selected range as ordered `(line, column)` start and end, if non-empty
+    pub fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        let anchor = self.selection_start?;
+        let cursor = (self.cursor_y, self.cursor_x);
+        match anchor.cmp(&cursor) {
+            std::cmp::Ordering::Less => Some((anchor, cursor)),
+            std::cmp::Ordering::Greater => Some((cursor, anchor)),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    /// Text covered by the selection, with lines joined by `\n`
+    fn selected_text(&self) -> Option<String> {
+        let ((start_y, start_x), (end_y, end_x)) = self.selection()?;
+        let mut text = String::new();
+        for y in start_y..=end_y.min(self.content.len().saturating_sub(1)) {
+            let line = &self.content[y];
+            let from = if y == start_y {
+                byte_index(line, start_x)
+            } else {
+                0
+            };
+            let to = if y == end_y {
+                byte_index(line, end_x)
+            } else {
+                line.len()
+            };
+            if y > start_y {
+                text.push('\n');
+            }
+            text.push_str(&line[from..to]);
+        }
+        Some(text)
+    }
+
+    /// Remove the selected text and place the cursor where it started.
+    /// Returns `false` when there is nothing selected.
+    fn delete_selection(&mut self) -> bool {
+        let Some(((start_y, start_x), (end_y, end_x))) = self.selection() else {
+            self.clear_selection();
+            return false;
+        };
+        if !self.begin_edit(EditKind::Selection) {
+            return false;
+        }
+        let end_y = end_y.min(self.content.len().saturating_sub(1));
+        let tail = {
+            let line = &self.content[end_y];
+            line[byte_index(line, end_x)..].to_string()
+        };
+        for _ in start_y..end_y {
+            self.content.remove(start_y + 1);
+        }
+        let line = &mut self.content[start_y];
+        line.truncate(byte_index(line, start_x));
+        line.push_str(&tail);
+        self.cursor_y = start_y;
+        self.cursor_x = start_x;
+        self.modified = true;
+        true
+    }
+
+    /// Remove and return the selection, or else the current line with its line break (Ctrl+X)
+    pub fn cut_text(&mut self) -> String {
+        let text = self.copy_text();
+        if !self.delete_selection() {
+            self.delete_line();
+        }
+        text
+    }
+
+    /// Remove the current line entirely (Ctrl+K)
+    pub fn delete_line(&mut self) {
+        if !self.begin_edit(EditKind::Line) {
+            return;
+        }
+        self.content
+            .remove(self.cursor_y.min(self.content.len() - 1));
+        if self.content.is_empty() {
+            self.content.push(String::new());
+        }
+        self.cursor_y = self.cursor_y.min(self.content.len() - 1);
+        self.cursor_x = 0;
+        self.modified = true;
+    }
+
+    /// Insert a copy of the current line below it and move onto the copy (Ctrl+D)
+    pub fn duplicate_line(&mut self) {
+        if !self.begin_edit(EditKind::Line) {
+            return;
+        }
+        let y = self.cursor_y.min(self.content.len() - 1);
+        self.content.insert(y, self.content[y].clone());
+        self.cursor_y = y + 1;
+        self.cursor_x = self.cursor_x.min(self.line_len(self.cursor_y));
+        self.modified = true;
+    }
+
+    /// Swap the current line with the one above (Alt+Up)
+    pub fn move_line_up(&mut self) {
+        if self.cursor_y == 0 || self.cursor_y >= self.content.len() {
+            return;
+        }
+        if !self.begin_edit(EditKind::Line) {
+            return;
+        }
+        self.content.swap(self.cursor_y, self.cursor_y - 1);
+        self.cursor_y -= 1;
+        self.cursor_x = self.cursor_x.min(self.line_len(self.cursor_y));
+        self.modified = true;
+    }
+
+    /// Swap the current line with the one below (Alt+Down)
+    pub fn move_line_down(&mut self) {
+        if self.cursor_y + 1 >= self.content.len() {
+            return;
+        }
+        if !self.begin_edit(EditKind::Line) {
+            return;
+        }
+        self.content.swap(self.cursor_y, self.cursor_y + 1);
+        self.cursor_y += 1;
+        self.cursor_x = self.cursor_x.min(self.line_len(self.cursor_y));
+        self.modified = true;
+    }
+
+    /// Append the next line to the current one with a single space between them, dropping
+    /// the whitespace around the join; the cursor is left at the join point (Ctrl+J)
+    pub fn join_line(&mut self) {
+        if self.cursor_y + 1 >= self.content.len() {
+            return;
+        }
+        if !self.begin_edit(EditKind::Line) {
+            return;
+        }
+        let next = self.content.remove(self.cursor_y + 1);
+        let next = next.trim_start();
+        let line = &mut self.content[self.cursor_y];
+        line.truncate(line.trim_end().len());
+        self.cursor_x = line.chars().count();
+        if !line.is_empty() && !next.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(next);
+        self.modified = true;
+    }
+
+    /// Hard-wrap the selected lines, or the paragraph around the cursor, so that no line is
+    /// wider than `width` columns unless a single word is (Alt+J). Each paragraph keeps the
+    /// indentation of its first line; blank lines between paragraphs are kept.
+    pub fn reflow(&mut self, width: usize) {
+        self.load_all();
+        let (first, last) = match self.selection() {
+            Some(_) => self
+                .selected_lines()
+                .unwrap_or((self.cursor_y, self.cursor_y)),
+            None if self.is_blank(self.cursor_y) => return,
+            None => {
+                let mut first = self.cursor_y;
+                while first > 0 && !self.is_blank(first - 1) {
+                    first -= 1;
+                }
+                let mut last = self.cursor_y;
+                while last + 1 < self.content.len() && !self.is_blank(last + 1) {
+                    last += 1;
+                }
+                (first, last)
+            }
+        };
+        let last = last.min(self.content.len() - 1);
+
+        let mut lines: Vec<String> = Vec::new();
+        let mut paragraph: Vec<&str> = Vec::new();
+        let mut indent = "";
+        for line in self.content.iter_from(first).take(last + 1 - first) {
+            if line.trim().is_empty() {
+                lines.extend(self.wrap_words(indent, &paragraph, width));
+                paragraph.clear();
+                lines.push(line.clone());
+                continue;
+            }
+            if paragraph.is_empty() {
+                indent = &line[..line.len() - line.trim_start().len()];
+            }
+            paragraph.extend(line.split_whitespace());
+        }
+        lines.extend(self.wrap_words(indent, &paragraph, width));
+
+        // Before touching the cursor, so undo puts it back and read-only buffers leave it
+        if !self.begin_edit(EditKind::Line) {
+            return;
+        }
+        self.invalidate_syntax(first);
+        for _ in first..=last {
+            self.content.remove(first);
+        }
+        let count = lines.len();
+        for (i, line) in lines.into_iter().enumerate() {
+            self.content.insert(first + i, line);
+        }
+        self.cursor_y = first + count - 1;
+        self.cursor_x = self.line_len(self.cursor_y);
+        self.modified = true;
+    }
+
+    /// Lay out `words` on lines starting with `indent`, each at most `width` columns wide
+    /// unless a single word is wider
+    fn wrap_words(&self, indent: &str, words: &[&str], width: usize) -> Vec<String> {
+        let indent_width = indent
+            .chars()
+            .fold(0, |col, c| col + char_width(c, col, self.tab_width));
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        let mut line_width = 0;
+        for word in words {
+            let word_width = word.chars().count();
+            if !line.is_empty() && line_width + 1 + word_width > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if line.is_empty() {
+                line.push_str(indent);
+                line_width = indent_width;
+            } else {
+                line.push(' ');
+                line_width += 1;
+            }
+            line.push_str(word);
+            line_width += word_width;
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Insert `tab_width` spaces at the cursor, or indent every line of a multi-line
+    /// selection (Tab key)
+    pub fn insert_tab(&mut self) {
+        if let Some(lines) = self.selected_lines() {
+            self.indent_lines(lines);
+            return;
+        }
+        if !self.use_spaces {
+            self.insert_char('\t');
+            return;
+        }
+        for _ in 0..self.tab_width {
+            self.insert_char(' ');
+        }
+    }
+
+    /// One level of indentation: `tab_width` spaces, or a tab without `use_spaces`
+    fn indent_unit(&self) -> String {
+        if self.use_spaces {
+            " ".repeat(self.tab_width)
+        } else {
+            "\t".to_string()
+        }
+    }
+
+    /// Characters at the start of `line` that make up one level of indentation: a tab,
+    /// or up to `tab_width` spaces
+    fn dedent_count(&self, line: &str) -> usize {
+        if line.starts_with('\t') {
+            return 1;
+        }
+        line.chars()
+            .take(self.tab_width)
+            .take_while(|&c| c == ' ')
+            .count()
+    }
+
+    /// First and last line of a selection spanning several lines. A selection ending
+    /// at column 0 doesn't include that line.
+    fn selected_lines(&self) -> Option<(usize, usize)> {
+        let ((start_y, _), (end_y, end_x)) = self.selection()?;
+        if start_y == end_y {
+            return None;
+        }
+        Some((start_y, if end_x == 0 { end_y - 1 } else { end_y }))
+    }
+
+    /// Indent lines `first..=last` one level, keeping the selection on them
+    fn indent_lines(&mut self, (first, last): (usize, usize)) {
+        let anchor = self.selection_start;
+        if !self.begin_edit(EditKind::Indent) {
+            return;
+        }
+        let indent = self.indent_unit();
+        for y in first..=last {
+            self.content[y].insert_str(0, &indent);
+        }
+        let shift = |(y, x): Position| {
+            if (first..=last).contains(&y) && x > 0 {
+                (y, x + indent.chars().count())
+            } else {
+                (y, x)
+            }
+        };
+        self.selection_start = anchor.map(shift);
+        (self.cursor_y, self.cursor_x) = shift((self.cursor_y, self.cursor_x));
+        self.modified = true;
+    }
+
+    /// Line comment prefix for the file type, from its extension or extensionless name
+    fn comment_prefix(&self) -> &str {
+        let path = Path::new(&self.filename);
+        let key = path
+            .extension()
+            .or_else(|| path.file_name())
+            .map(|s| s.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        self.comment_prefixes
+            .iter()
+            .find(|(ext, _)| *ext == key)
+            .map_or(FALLBACK_COMMENT_PREFIX, |(_, prefix)| prefix.as_str())
+    }
+
+    /// Comment out the current line or the selected lines, or uncomment them when every
+    /// non-blank one is already commented (Ctrl+/). The prefix goes after the indentation.
+    pub fn toggle_comment(&mut self) {
+        let (first, last) = self
+            .selected_lines()
+            .unwrap_or((self.cursor_y, self.cursor_y));
+        let last = last.min(self.content.len().saturating_sub(1));
+        let prefix = self.comment_prefix().to_string();
+        // Indentation width in characters of each non-blank target line
+        let targets: Vec<(usize, usize)> = (first..=last)
+            .filter_map(|y| {
+                let line = &self.content[y];
+                let indent = line.chars().take_while(|c| c.is_whitespace()).count();
+                (indent < line.chars().count()).then_some((y, indent))
+            })
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+        let uncomment = targets.iter().all(|&(y, indent)| {
+            let line = &self.content[y];
+            line[byte_index(line, indent)..].starts_with(&prefix)
+        });
+        let anchor = self.selection_start;
+        if !self.begin_edit(EditKind::Comment) {
+            return;
+        }
+
+        // Characters inserted (positive) or removed (negative) at each line's indentation
+        let mut changes = Vec::new();
+        for &(y, indent) in &targets {
+            let line = &mut self.content[y];
+            let at = byte_index(line, indent);
+            let delta = if uncomment {
+                let rest = &line[at + prefix.len()..];
+                let len = prefix.len() + usize::from(rest.starts_with(' '));
+                line.drain(at..at + len);
+                -(prefix.chars().count() as isize + (len - prefix.len()) as isize)
+            } else {
+                line.insert_str(at, &format!("{} ", prefix));
+                prefix.chars().count() as isize + 1
+            };
+            changes.push((y, indent, delta));
+        }
+        let shift = |(y, x): Position| match changes.iter().find(|c| c.0 == y) {
+            Some(&(_, indent, delta)) if x > indent => {
+                (y, x.saturating_add_signed(delta).max(indent))
+            }
+            _ => (y, x),
+        };
+        self.selection_start = anchor.map(shift);
+        (self.cursor_y, self.cursor_x) = shift((self.cursor_y, self.cursor_x));
+        self.modified = true;
+    }
+
+    /// Remove one level of indentation from lines `first..=last`, keeping the selection
+    /// on them
+    fn dedent_lines(&mut self, (first, last): (usize, usize)) {
+        let removed: Vec<usize> = (first..=last)
+            .map(|y| self.dedent_count(&self.content[y]))
+            .collect();
+        if removed.iter().all(|&n| n == 0) {
+            return;
+        }
+        let anchor = self.selection_start;
+        if !self.begin_edit(EditKind::Indent) {
+            return;
+        }
+        for (y, &count) in (first..).zip(&removed) {
+            self.content[y].drain(..count);
+        }
+        let shift = |(y, x): Position| match y.checked_sub(first).and_then(|i| removed.get(i)) {
+            Some(&count) => (y, x.saturating_sub(count)),
+            None => (y, x),
+        };
+        self.selection_start = anchor.map(shift);
+        (self.cursor_y, self.cursor_x) = shift((self.cursor_y, self.cursor_x));
+        self.modified = true;
+    }
+
+    /// Remove one level of indentation from the current line, or from every line of a
+    /// multi-line selection (Shift+Tab)
+    pub fn dedent_line(&mut self) {
+        if let Some(lines) = self.selected_lines() {
+            self.dedent_lines(lines);
+            return;
+        }
+        let Some(line) = self.current_line() else {
+            return;
+        };
+        let count = self.dedent_count(line);
+        if count == 0 {
+            return;
+        }
+        if !self.begin_edit(EditKind::Indent) {
+            return;
+        }
+        self.content[self.cursor_y].drain(..count);
+        self.cursor_x = self.cursor_x.saturating_sub(count);
+        self.modified = true;
+    }
+
+    /// Add `delta` to the number under or after the cursor on the current line, keeping
+    /// leading zeros and a minus sign in front of it. The cursor ends on its last digit.
+    pub fn increment_number(&mut self, delta: i128) {
+        let Some(line) = self.current_line() else {
+            return;
+        };
+        let chars: Vec<char> = line.chars().collect();
+        let x = self.cursor_x.min(chars.len());
+        // Right after a number counts as on it
+        let from = if x > 0
+            && !chars.get(x).is_some_and(char::is_ascii_digit)
+            && chars[x - 1].is_ascii_digit()
+        {
+            x - 1
+        } else {
+            x
+        };
+        let Some(at) = (from..chars.len()).find(|&i| chars[i].is_ascii_digit()) else {
+            self.set_status("No number at the cursor");
+            return;
+        };
+        let mut start = at;
+        while start > 0 && chars[start - 1].is_ascii_digit() {
+            start -= 1;
+        }
+        let mut end = at;
+        while end < chars.len() && chars[end].is_ascii_digit() {
+            end += 1;
+        }
+        // A minus sign counts unless it follows a word, as in `a-1`
+        let negative =
+            start > 0 && chars[start - 1] == '-' && (start < 2 || !is_word_char(chars[start - 2]));
+        let digits: String = chars[start..end].iter().collect();
+        let Ok(magnitude) = digits.parse::<i128>() else {
+            self.set_status("Number too large");
+            return;
+        };
+        let value = if negative { -magnitude } else { magnitude };
+        let Some(new) = value.checked_add(delta) else {
+            self.set_status("Number too large");
+            return;
+        };
+        let width = if digits.starts_with('0') {
+            digits.len()
+        } else {
+            0
+        };
+        let sign = if new < 0 { "-" } else { "" };
+        let text = format!("{}{:0width$}", sign, new.unsigned_abs(), width = width);
+        let start = if negative { start - 1 } else { start };
+        if !self.begin_edit(EditKind::Number) {
+            return;
+        }
+        let line = &mut self.content[self.cursor_y];
+        let range = byte_index(line, start)..byte_index(line, end);
+        line.replace_range(range, &text);
+        self.cursor_x = start + text.chars().count() - 1;
+        self.modified = true;
+    }
+
+    /// Delete a character (Backspace)
+    pub fn delete_char(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+        if self.cursor_x == 0 && self.cursor_y == 0 {
+            return;
+        }
+        // Deleting the opening half of an empty pair removes the closing half too
+        let empty_pair = self.cursor_x > 0
+            && self.auto_pairs.iter().any(|&(open, close)| {
+                self.char_at(self.cursor_y, self.cursor_x - 1) == Some(open)
+                    && self.char_at(self.cursor_y, self.cursor_x) == Some(close)
+            });
+        if !self.begin_edit(EditKind::Delete) {
+            return;
+        }
+        if self.cursor_y < self.content.len() && self.cursor_x > 0 {
+            let line = &mut self.content[self.cursor_y];
+            let idx = byte_index(line, self.cursor_x - 1);
+            line.remove(idx);
+            if empty_pair {
+                line.remove(idx);
+            }
+            self.cursor_x -= 1;
+            self.modified = true;
+        } else if self.cursor_y > 0 {
+            let current = self.content.remove(self.cursor_y);
+            self.cursor_y -= 1;
+            self.cursor_x = self.line_len(self.cursor_y);
+            self.content[self.cursor_y].push_str(&current);
+            self.modified = true;
+        }
+    }
+
+    /// Delete back to the start of the previous word, joining the line to the previous one
+    /// at column 0 (Ctrl+Backspace)
+    pub fn delete_word_back(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+        if self.cursor_x == 0 {
+            self.delete_char();
+            return;
+        }
+        let start = self.word_start_before(self.cursor_y, self.cursor_x);
+        if !self.begin_edit(EditKind::DeleteWord) {
+            return;
+        }
+        let line = &mut self.content[self.cursor_y];
+        let range = byte_index(line, start)..byte_index(line, self.cursor_x);
+        line.replace_range(range, "");
+        self.cursor_x = start;
+        self.modified = true;
+    }
+
+    /// Delete the character under the cursor, joining the next line at end of line (Delete)
+    pub fn delete_forward(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+        let len = self.line_len(self.cursor_y);
+        if self.cursor_x >= len && self.cursor_y + 1 >= self.content.len() {
+            return;
+        }
+        if !self.begin_edit(EditKind::DeleteForward) {
+            return;
+        }
+        if self.cursor_x < len {
+            let line = &mut self.content[self.cursor_y];
+            line.remove(byte_index(line, self.cursor_x));
+        } else {
+            let next = self.content.remove(self.cursor_y + 1);
+            self.content[self.cursor_y].push_str(&next);
+            self.cursor_x = len;
+        }
+        self.modified = true;
+    }
+
+    /// Count lines, whitespace-separated words and characters in the whole buffer
+    pub fn stats(&mut self) -> DocumentStats {
+        self.load_all();
+        let mut stats = DocumentStats {
+            lines: self.content.len(),
+            words: 0,
+            chars: 0,
+            chars_no_whitespace: 0,
+        };
+        for line in self.content.iter() {
+            stats.words += line.split_whitespace().count();
+            for ch in line.chars() {
+                stats.chars += 1;
+                if !ch.is_whitespace() {
+                    stats.chars_no_whitespace += 1;
+                }
+            }
+        }
+        stats
+    }
+
+    /// Move the cursor (with basic bounds and scrolling). `visible_width` is the width of
+    /// the text area, which decides where soft wrapped lines break.
+    pub fn move_cursor(&mut self, motion: Motion, visible_height: usize, visible_width: usize) {
+        self.ensure_loaded(self.cursor_y + visible_height + 1);
+        let len = self.current_line().map_or(0, |l| l.chars().count());
+        self.last_edit = None;
+        let vertical = matches!(
+            motion,
+            Motion::Up | Motion::Down | Motion::PageUp | Motion::PageDown
+        );
+        // Soft wrapped rows keep their own goal in `move_display_row`
+        let goal = if self.soft_wrap {
+            self.display_col(self.cursor_y, self.cursor_x)
+        } else {
+            self.goal_column(self.display_col(self.cursor_y, self.cursor_x))
+        };
+        match motion {
+            Motion::Up | Motion::Down if self.soft_wrap => {
+                self.move_display_row(motion == Motion::Down, visible_width)
+            }
+            Motion::Up if self.cursor_y > 0 => {
+                self.cursor_y -= 1;
+                if self.cursor_y < self.scroll_y {
+                    self.scroll_y -= 1;
+                }
+                self.cursor_x = self.col_to_char(self.cursor_y, goal);
+            }
+            Motion::Down if self.cursor_y + 1 < self.content.len() => {
+                self.cursor_y += 1;
+                if self.cursor_y >= self.scroll_y + visible_height {
+                    self.scroll_y += 1;
+                }
+                self.cursor_x = self.col_to_char(self.cursor_y, goal);
+            }
+            Motion::Left => {
+                if self.cursor_x > 0 {
+                    self.cursor_x -= 1;
+                } else if self.cursor_y > 0 {
+                    self.cursor_y -= 1;
+                    self.cursor_x = self.line_len(self.cursor_y);
+                }
+            }
+            Motion::Right => {
+                if self.cursor_x < len {
+                    self.cursor_x += 1;
+                } else if self.cursor_y + 1 < self.content.len() {
+                    self.cursor_y += 1;
+                    self.cursor_x = 0;
+                }
+            }
+            Motion::Home => {
+                // Smart home: first non-blank character, then column 0 on a second press
+                let indent = self
+                    .current_line()
+                    .map_or(0, |l| l.chars().take_while(|c| c.is_whitespace()).count());
+                self.cursor_x = if self.cursor_x == indent { 0 } else { indent };
+            }
+            Motion::End => self.cursor_x = len,
+            Motion::PageUp => {
+                let page = visible_height.max(1);
+                self.cursor_y = self.cursor_y.saturating_sub(page);
+                self.scroll_y = self.scroll_y.saturating_sub(page).min(self.cursor_y);
+                self.cursor_x = self.col_to_char(self.cursor_y, goal);
+            }
+            Motion::PageDown => {
+                let page = visible_height.max(1);
+                let last = self.content.len().saturating_sub(1);
+                self.cursor_y = (self.cursor_y + page).min(last);
+                self.scroll_y = (self.scroll_y + page).min(last.saturating_sub(page - 1));
+                if self.cursor_y >= self.scroll_y + page {
+                    self.scroll_y = self.cursor_y + 1 - page;
+                }
+                self.cursor_x = self.col_to_char(self.cursor_y, goal);
+            }
+            _ => {}
+        }
+        if vertical && !self.soft_wrap {
+            self.goal_col = Some(((self.cursor_y, self.cursor_x), goal));
+        }
+    }
+
+    /// Remembered goal column if the cursor hasn't moved since the last vertical move,
+    /// otherwise `current`
+    fn goal_column(&self, current: usize) -> usize {
+        match self.goal_col {
+            Some((position, goal)) if position == (self.cursor_y, self.cursor_x) => goal,
+            _ => current,
+        }
+    }
+
+    /// Move the cursor one screen row up or down through soft wrapped lines, keeping its
+    /// column within the row where the new row is long enough
+    fn move_display_row(&mut self, down: bool, width: usize) {
+        let (row, col) = self.wrap_position(self.cursor_y, self.cursor_x, width);
+        let col = self.goal_column(col);
+        let (y, row) = if down {
+            if row + 1 < self.line_rows(self.cursor_y, width) {
+                (self.cursor_y, row + 1)
+            } else if self.cursor_y + 1 < self.content.len() {
+                (self.cursor_y + 1, 0)
+            } else {
+                return;
+            }
+        } else if row > 0 {
+            (self.cursor_y, row - 1)
+        } else if self.cursor_y > 0 {
+            (
+                self.cursor_y - 1,
+                self.line_rows(self.cursor_y - 1, width) - 1,
+            )
+        } else {
+            return;
+        };
+        self.cursor_y = y;
+        self.cursor_x = self.wrap_char(y, row, col, width);
+        self.goal_col = Some(((self.cursor_y, self.cursor_x), col));
+    }
+
+    /// Move to the start of the previous word, crossing to the previous line at column 0
+    pub fn word_left(&mut self) {
+        self.last_edit = None;
+        if self.cursor_x == 0 {
+            if self.cursor_y > 0 {
+                self.cursor_y -= 1;
+                self.cursor_x = self.line_len(self.cursor_y);
+            }
+            return;
+        }
+        self.cursor_x = self.word_start_before(self.cursor_y, self.cursor_x);
+    }
+
+    /// Start of the word before column `x` on line `y`, skipping any non-word characters
+    /// in between
+    fn word_start_before(&self, y: usize, x: usize) -> usize {
+        let chars: Vec<char> = self
+            .content
+            .get(y)
+            .map_or(Vec::new(), |l| l.chars().collect());
+        let mut x = x.min(chars.len());
+        while x > 0 && !is_word_char(chars[x - 1]) {
+            x -= 1;
+        }
+        while x > 0 && is_word_char(chars[x - 1]) {
+            x -= 1;
+        }
+        x
+    }
+
+    /// Move to the end of the next word, crossing to the next line at end of line
+    pub fn word_right(&mut self) {
+        self.last_edit = None;
+        let len = self.line_len(self.cursor_y);
+        if self.cursor_x >= len {
+            if self.cursor_y + 1 < self.content.len() {
+                self.cursor_y += 1;
+                self.cursor_x = 0;
+            }
+            return;
+        }
+        let chars: Vec<char> = self.content[self.cursor_y].chars().collect();
+        let mut x = self.cursor_x;
+        while x < len && !is_word_char(chars[x]) {
+            x += 1;
+        }
+        while x < len && is_word_char(chars[x]) {
+            x += 1;
+        }
+        self.cursor_x = x;
+    }
+
+    /// Whether line `y` is empty or only whitespace, which separates paragraphs
+    fn is_blank(&self, y: usize) -> bool {
+        self.content.get(y).is_some_and(|l| l.trim().is_empty())
+    }
+
+    /// Move to the blank line before the current paragraph, or the first line (Ctrl+Up)
+    pub fn paragraph_up(&mut self) {
+        self.last_edit = None;
+        let mut y = self.cursor_y.saturating_sub(1);
+        while y > 0 && self.is_blank(y) {
+            y -= 1;
+        }
+        while y > 0 && !self.is_blank(y) {
+            y -= 1;
+        }
+        self.cursor_y = y;
+        self.cursor_x = 0;
+    }
+
+    /// Move to the blank line after the current paragraph, or the last line (Ctrl+Down)
+    pub fn paragraph_down(&mut self) {
+        self.load_all();
+        self.last_edit = None;
+        let last = self.content.len().saturating_sub(1);
+        let mut y = (self.cursor_y + 1).min(last);
+        while y < last && self.is_blank(y) {
+            y += 1;
+        }
+        while y < last && !self.is_blank(y) {
+            y += 1;
+        }
+        self.cursor_y = y;
+        self.cursor_x = 0;
+    }
+
+    /// Move to 1-based line `line` and `column`, or the start of the line without a
+    /// column. Both are clamped to the buffer (Ctrl+G).
+    pub fn goto_line(&mut self, line: usize, column: Option<usize>, visible_height: usize) {
+        self.load_all();
+        self.cursor_y = line
+            .saturating_sub(1)
+            .min(self.content.len().saturating_sub(1));
+        self.cursor_x = column.map_or(0, |column| {
+            column.saturating_sub(1).min(self.line_len(self.cursor_y))
+        });
+        self.last_edit = None;
+        self.center_on_cursor(visible_height);
+    }
+
+    /// Jump to the first character of the buffer (Ctrl+Home)
+    pub fn goto_start(&mut self) {
+        self.cursor_y = 0;
+        self.cursor_x = 0;
+        self.scroll_y = 0;
+        self.last_edit = None;
+    }
+
+    /// Jump to the end of the last line (Ctrl+End)
+    pub fn goto_end(&mut self, visible_height: usize) {
+        self.load_all();
+        self.cursor_y = self.content.len().saturating_sub(1);
+        self.cursor_x = self.line_len(self.cursor_y);
+        self.scroll_y = (self.cursor_y + 1).saturating_sub(visible_height.max(1));
+        self.last_edit = None;
+    }
+
+    fn current_line(&self) -> Option<&String> {
+        self.content.get(self.cursor_y)
+    }
+
+    /// Width of the line number gutter, including its trailing space (0 when hidden)
+    pub fn gutter_width(&self) -> usize {
+        if !self.show_line_numbers {
+            return 0;
+        }
+        // One column for the change bar and one between the number and the text
+        self.content.len().max(1).to_string().len() + 2
+    }
+
+    /// How each line differs from the file as it was opened or last saved. Lines that
+    /// replace removed ones count as modified, any beyond those as added. The result is
+    /// kept until the next edit, so redrawing an unchanged buffer doesn't compare again.
+    pub fn line_changes(&self) -> Rc<[LineChange]> {
+        self.changes
+            .borrow_mut()
+            .get_or_insert_with(|| self.compare_with_original().into())
+            .clone()
+    }
+
+    fn forget_line_changes(&mut self) {
+        *self.changes.get_mut() = None;
+    }
+
+    fn compare_with_original(&self) -> Vec<LineChange> {
+        let current: Vec<&String> = self.content.iter().collect();
+        let mut changes = vec![LineChange::Unchanged; current.len()];
+        if !self.modified {
+            return changes;
+        }
+        let original: Vec<&String> = self.original.iter().collect();
+        let prefix = current
+            .iter()
+            .zip(&original)
+            .take_while(|&(a, b)| a == b)
+            .count();
+        let suffix = current[prefix..]
+            .iter()
+            .rev()
+            .zip(original[prefix..].iter().rev())
+            .take_while(|&(a, b)| a == b)
+            .count();
+        let current = &current[prefix..current.len() - suffix];
+        let original = &original[prefix..original.len() - suffix];
+        if current.len() * original.len() > MAX_DIFF_CELLS {
+            changes[prefix..prefix + current.len()].fill(LineChange::Modified);
+            return changes;
+        }
+
+        // Length of the longest common subsequence of `current[i..]` and `original[j..]`
+        let width = original.len() + 1;
+        let mut common = vec![0u32; (current.len() + 1) * width];
+        for i in (0..current.len()).rev() {
+            for j in (0..original.len()).rev() {
+                common[i * width + j] = if current[i] == original[j] {
+                    common[(i + 1) * width + j + 1] + 1
+                } else {
+                    common[(i + 1) * width + j].max(common[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        // Original lines skipped since the last common one, which changed lines replace
+        let mut removed = 0;
+        while i < current.len() {
+            if j < original.len() && current[i] == original[j] {
+                (i, j, removed) = (i + 1, j + 1, 0);
+            } else if j < original.len() && common[i * width + j + 1] >= common[(i + 1) * width + j]
+            {
+                j += 1;
+                removed += 1;
+            } else {
+                changes[prefix + i] = if removed > 0 {
+                    removed -= 1;
+                    LineChange::Modified
+                } else {
+                    LineChange::Added
+                };
+                i += 1;
+            }
+        }
+        changes
+    }
+
+    /// Screen column of character `x` on line `y`, with tabs expanded to the next tab stop
+    pub fn display_col(&self, y: usize, x: usize) -> usize {
+        self.content.get(y).map_or(x, |line| {
+            let mut col = 0;
+            for c in line.chars().take(x) {
+                col += char_width(c, col, self.tab_width);
+            }
+            col + x.saturating_sub(line.chars().count())
+        })
+    }
+
+    /// Character index on line `y` shown at display column `col`, clamped to the line end
+    fn col_to_char(&self, y: usize, col: usize) -> usize {
+        let Some(line) = self.content.get(y) else {
+            return 0;
+        };
+        let mut width = 0;
+        for (i, c) in line.chars().enumerate() {
+            width += char_width(c, width, self.tab_width);
+            if width > col {
+                return i;
+            }
+        }
+        line.chars().count()
+    }
+
+    /// Length of a line in characters (0 for lines past the end of the buffer)
+    fn line_len(&self, y: usize) -> usize {
+        self.content.get(y).map_or(0, |l| l.chars().count())
+    }
+
+    /// Character indices where each screen row of line `y` starts when the text area is
+    /// `width` columns wide. Without soft wrap every line is a single row. A line that
+    /// exactly fills its last row gets an empty row after it, where the cursor goes at the
+    /// end of the line.
+    pub fn wrap_starts(&self, y: usize, width: usize) -> Vec<usize> {
+        let mut starts = vec![0];
+        let Some(line) = self.content.get(y) else {
+            return starts;
+        };
+        if !self.soft_wrap || width == 0 {
+            return starts;
+        }
+        // Tab stops are counted from the start of the line, not of the row
+        let (mut col, mut row_start) = (0, 0);
+        for (i, c) in line.chars().enumerate() {
+            let w = char_width(c, col, self.tab_width);
+            if col > row_start && col + w - row_start > width {
+                starts.push(i);
+                row_start = col;
+            }
+            col += w;
+        }
+        if col - row_start >= width {
+            starts.push(line.chars().count());
+        }
+        starts
+    }
+
+    /// Number of screen rows line `y` takes up
+    fn line_rows(&self, y: usize, width: usize) -> usize {
+        self.wrap_starts(y, width).len()
+    }
+
+    /// Screen row within line `y` holding character `x`, and its column within that row
+    fn wrap_position(&self, y: usize, x: usize, width: usize) -> (usize, usize) {
+        let starts = self.wrap_starts(y, width);
+        let row = starts.iter().rposition(|&start| start <= x).unwrap_or(0);
+        let col = self.display_col(y, x) - self.display_col(y, starts[row]);
+        (row, col)
+    }
+
+    /// Character index shown at column `col` of screen row `row` of line `y`, clamped to
+    /// the end of that row
+    fn wrap_char(&self, y: usize, row: usize, col: usize, width: usize) -> usize {
+        let starts = self.wrap_starts(y, width);
+        let row = row.min(starts.len() - 1);
+        let x = self.col_to_char(y, self.display_col(y, starts[row]) + col);
+        match starts.get(row + 1) {
+            // Stay on the last character of a continuation row rather than the next row
+            Some(&next) => x.min(next - 1),
+            None => x,
+        }
+    }
+
+    /// Cursor position within the text area, counted from its top left corner, if it is
+    /// on screen. With soft wrap the rows of the wrapped lines above it are counted.
+    pub fn cursor_on_screen(&self, visible_height: usize, width: usize) -> Option<(usize, usize)> {
+        let lines_above = self.cursor_y.checked_sub(self.scroll_y)?;
+        if lines_above >= visible_height {
+            return None;
+        }
+        let (row, col) = if self.soft_wrap {
+            let (row, col) = self.wrap_position(self.cursor_y, self.cursor_x, width);
+            let rows_above: usize = (self.scroll_y..self.cursor_y)
+                .map(|y| self.line_rows(y, width))
+                .sum();
+            (rows_above + row, col)
+        } else {
+            let col = self.display_col(self.cursor_y, self.cursor_x);
+            (lines_above, col.checked_sub(self.scroll_x)?)
+        };
+        (row < visible_height && col < width).then_some((row, col))
+    }
+
+    /// Buffer position shown at `row` and `col` of the text area, clamped to the text
+    pub fn position_at(&self, row: usize, col: usize, width: usize) -> Position {
+        let last = self.content.len().saturating_sub(1);
+        if !self.soft_wrap {
+            let y = (self.scroll_y + row).min(last);
+            return (y, self.col_to_char(y, col + self.scroll_x));
+        }
+        let (mut y, mut row) = (self.scroll_y.min(last), row);
+        loop {
+            let rows = self.line_rows(y, width);
+            if row < rows || y == last {
+                return (y, self.wrap_char(y, row, col, width));
+            }
+            row -= rows;
+            y += 1;
+        }
+    }
+
+    /// Adjust the vertical scroll so the cursor line stays on screen. With soft wrap the
+    /// cursor's row of a wrapped line has to fit as well.
+    pub fn scroll_y_to_cursor(&mut self, visible_height: usize, visible_width: usize) {
+        if self.cursor_y < self.scroll_y {
+            self.scroll_y = self.cursor_y;
+        } else if visible_height > 0 && self.cursor_y >= self.scroll_y + visible_height {
+            self.scroll_y = self.cursor_y + 1 - visible_height;
+        }
+        if self.soft_wrap && visible_height > 0 {
+            let (row, _) = self.wrap_position(self.cursor_y, self.cursor_x, visible_width);
+            let mut rows = row
+                + 1
+                + (self.scroll_y..self.cursor_y)
+                    .map(|y| self.line_rows(y, visible_width))
+                    .sum::<usize>();
+            while rows > visible_height && self.scroll_y < self.cursor_y {
+                rows -= self.line_rows(self.scroll_y, visible_width);
+                self.scroll_y += 1;
+            }
+        }
+    }
+
+    /// Adjust the horizontal scroll so the cursor column stays on screen; soft wrapped
+    /// text never scrolls sideways
+    pub fn scroll_x_to_cursor(&mut self, visible_width: usize) {
+        if self.soft_wrap {
+            self.scroll_x = 0;
+            return;
+        }
+        let col = self.display_col(self.cursor_y, self.cursor_x);
+        if col < self.scroll_x {
+            self.scroll_x = col;
+        } else if visible_width > 0 && col >= self.scroll_x + visible_width {
+            self.scroll_x = col + 1 - visible_width;
+        }
+    }
+
+    /// Scroll the view by `delta` lines, dragging the cursor along so it stays visible
+    pub fn scroll_by(&mut self, delta: isize, visible_height: usize) {
+        let last = self.content.len().saturating_sub(1);
+        self.scroll_y = self.scroll_y.saturating_add_signed(delta).min(last);
+        let bottom = self.scroll_y + visible_height.saturating_sub(1);
+        self.cursor_y = self
+            .cursor_y
+            .clamp(self.scroll_y, bottom.max(self.scroll_y));
+        self.cursor_x = self.cursor_x.min(self.line_len(self.cursor_y));
+    }
+
+    /// Center the viewport on the cursor line if it is currently off-screen
+    fn center_on_cursor(&mut self, visible_height: usize) {
+        if self.cursor_y < self.scroll_y || self.cursor_y >= self.scroll_y + visible_height {
+            self.scroll_y = self.cursor_y.saturating_sub(visible_height / 2);
+        }
+    }
+
+    /// Make `query` the search query, compiling it when regex search is on.
+    /// An invalid regex clears the search and is returned as an error message.
+    pub fn set_search_query(&mut self, query: String) -> Result<(), String> {
+        self.search_regex = None;
+        self.search_query = None;
+        if self.regex_search {
+            self.search_regex = Some(Regex::new(&query, !self.case_sensitive)?);
+        }
+        self.search_query = Some(query);
+        Ok(())
+    }
+
+    /// Search for `query` starting at `origin` and move the cursor onto the first match,
+    /// wrapping to the top. Returns whether there was a match.
+    pub fn search(
+        &mut self,
+        query: String,
+        origin: Position,
+        visible_height: usize,
+    ) -> Result<bool, String> {
+        self.load_all();
+        if let Err(e) = self.set_search_query(query) {
+            self.current_match = None;
+            return Err(e);
+        }
+        match self.find_from(origin.0, origin.1) {
+            Some(m) => {
+                self.jump_to_match(m, visible_height);
+                Ok(true)
+            }
+            None => {
+                self.current_match = None;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Jump to the next occurrence of the last search query, wrapping at the end
+    pub fn find_next(&mut self, visible_height: usize) {
+        self.load_all();
+        if self.search_query.is_none() {
+            return;
+        }
+        match self.find_from(self.cursor_y, self.cursor_x + 1) {
+            Some(m) => {
+                if (m.y, m.x) <= (self.cursor_y, self.cursor_x) {
+                    self.set_status("Search wrapped to top");
+                }
+                self.jump_to_match(m, visible_height);
+            }
+            None => self.set_status("No more matches"),
+        }
+    }
+
+    /// Move the cursor onto a match and make it the highlighted one
+    pub fn jump_to_match(&mut self, m: SearchMatch, visible_height: usize) {
+        self.cursor_y = m.y;
+        self.cursor_x = m.x;
+        self.current_match = Some(m);
+        self.match_position = self.count_matches(m);
+        self.last_edit = None;
+        self.center_on_cursor(visible_height);
+    }
+
+    /// Position of `m` among all matches in the buffer as `(index, total)`
+    fn count_matches(&self, m: SearchMatch) -> (usize, usize) {
+        let (mut index, mut total) = (0, 0);
+        for y in 0..self.content.len() {
+            for other in self.matches_in_line(y) {
+                total += 1;
+                if (other.y, other.x) <= (m.y, m.x) {
+                    index = total;
+                }
+            }
+        }
+        (index, total)
+    }
+
+    /// Find the first match at or after `(y, x)`, wrapping around to the top
+    fn find_from(&self, y: usize, x: usize) -> Option<SearchMatch> {
+        self.find_forward(y, x).or_else(|| self.find_forward(0, 0))
+    }
+
+    /// Find the first match at or after `(y, x)` without wrapping
+    pub fn find_forward(&self, y: usize, x: usize) -> Option<SearchMatch> {
+        self.search_query.as_ref()?;
+        self.content
+            .iter_from(y)
+            .zip(y..)
+            .find_map(|(line, line_y)| {
+                let from = if line_y == y { x } else { 0 };
+                self.match_in_line(line, from)
+                    .map(|(x, len)| SearchMatch { y: line_y, x, len })
+            })
+    }
+
+    /// First match of the search query in `line` at or after character `from`,
+    /// as `(column, length)`
+    fn match_in_line(&self, line: &str, from: usize) -> Option<(usize, usize)> {
+        match &self.search_regex {
+            Some(regex) => regex.find_at(&line.chars().collect::<Vec<_>>(), from),
+            None => find_in_line(
+                line,
+                self.search_query.as_deref()?,
+                from,
+                self.case_sensitive,
+            ),
+        }
+    }
+
+    /// All matches of the search query on line `y`, left to right
+    pub fn matches_in_line(&self, y: usize) -> Vec<SearchMatch> {
+        let (Some(_), Some(line)) = (self.search_query.as_deref(), self.content.get(y)) else {
+            return Vec::new();
+        };
+        let mut matches = Vec::new();
+        let mut from = 0;
+        while let Some((x, len)) = self.match_in_line(line, from) {
+            if len == 0 {
+                break;
+            }
+            matches.push(SearchMatch { y, x, len });
+            from = x + len;
+        }
+        matches
+    }
+
+    /// Forget the search query and its highlights
+    pub fn clear_search(&mut self) {
+        self.search_query = None;
+        self.search_regex = None;
+        self.current_match = None;
+    }
+
+    /// Replace the text covered by `m` and return the length of the replacement in characters
+    pub fn replace_match(&mut self, m: SearchMatch, replacement: &str) -> usize {
+        if !self.begin_edit(EditKind::Replace) {
+            return 0;
+        }
+        self.invalidate_syntax(m.y);
+        let line = &mut self.content[m.y];
+        let start = byte_index(line, m.x);
+        let end = byte_index(line, m.x + m.len);
+        line.replace_range(start..end, replacement);
+        self.cursor_y = m.y;
+        self.cursor_x = m.x;
+        self.modified = true;
+        replacement.chars().count()
+    }
+
+    /// Replace matches of `query` on the cursor line, or on every line when `whole_file`
+    /// is set. Only the first match of each line is replaced unless `global` is set.
+    /// Returns the number of replacements, all undone in one step.
+    pub fn substitute(
+        &mut self,
+        query: String,
+        replacement: &str,
+        whole_file: bool,
+        global: bool,
+    ) -> Result<usize, String> {
+        if !self.can_edit() {
+            return Ok(0);
+        }
+        self.load_all();
+        self.set_search_query(query)?;
+        self.current_match = None;
+        self.end_undo_step();
+        let lines = if whole_file {
+            0..self.content.len()
+        } else {
+            self.cursor_y..self.cursor_y + 1
+        };
+        let (cursor_y, cursor_x) = (self.cursor_y, self.cursor_x);
+        let mut replaced = 0;
+        for y in lines {
+            let mut x = 0;
+            while x <= self.content[y].chars().count()
+                && let Some((start, len)) = self.match_in_line(&self.content[y], x)
+            {
+                let m = SearchMatch { y, x: start, len };
+                // Step past empty matches so they aren't found again
+                x = start + self.replace_match(m, replacement) + usize::from(len == 0);
+                replaced += 1;
+                if !global {
+                    break;
+                }
+            }
+        }
+        self.cursor_y = cursor_y;
+        self.cursor_x = cursor_x.min(self.content[cursor_y].chars().count());
+        self.last_edit = None;
+        Ok(replaced)
+    }
+}
+
+/// Last modification time of `path`, if it exists
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// `dir/.name.swp` for `dir/name`
+fn swap_path_for(filename: &str) -> String {
+    let path = Path::new(filename);
+    let name = path
+        .file_name()
+        .map_or_else(|| filename.into(), |n| n.to_string_lossy());
+    path.with_file_name(format!(".{}.swp", name))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Characters that make up a word for word-wise movement and deletion
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Find `query` in `line` starting at character `from`.
+/// Returns the match column and length, both in characters of the original line.
+fn find_in_line(
+    line: &str,
+    query: &str,
+    from: usize,
+    case_sensitive: bool,
+) -> Option<(usize, usize)> {
+    if case_sensitive {
+        let start = byte_index(line, from);
+        return line[start..]
+            .find(query)
+            .map(|b| (line[..start + b].chars().count(), query.chars().count()));
+    }
+
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    let chars: Vec<char> = line.chars().collect();
+    (from..chars.len()).find_map(|start| {
+        // Lowercase the line lazily so the column refers to the original characters
+        let mut matched = 0;
+        let mut consumed = 0;
+        for ch in &chars[start..] {
+            if matched == query.len() {
+                break;
+            }
+            for lower in ch.to_lowercase() {
+                if query.get(matched) != Some(&lower) {
+                    return None;
+                }
+                matched += 1;
+            }
+            consumed += 1;
+        }
+        (matched == query.len()).then_some((start, consumed))
+    })
+}
+
+/// Convert a character index into a byte offset within `line`.
+/// Indexes past the end map to the line's byte length.
+fn byte_index(line: &str, char_idx: usize) -> usize {
+    line.char_indices()
+        .nth(char_idx)
+        .map_or(line.len(), |(b, _)| b)
+}
+
+/// Display width of `c` at screen column `col`: a tab reaches the next multiple of
+/// `tab_width`, anything else takes one column
+pub fn char_width(c: char, col: usize, tab_width: usize) -> usize {
+    match c {
+        '\t' if tab_width > 0 => tab_width - col % tab_width,
+        '\t' => 0,
+        _ => 1,
+    }
+}
+
+/// `line` without its trailing spaces and tabs, the whitespace that is highlighted and
+/// stripped on save
+pub fn trim_trailing_whitespace(line: &str) -> &str {
+    line.trim_end_matches([' ', '\t'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(lines: &[&str]) -> Editor {
+        Editor::from_lines(lines.iter().map(|l| l.to_string()).collect())
+    }
+
+    fn lines(editor: &Editor) -> Vec<&str> {
+        editor.content.iter().map(String::as_str).collect()
+    }
+
+    fn cursor(editor: &Editor) -> Position {
+        (editor.cursor_y, editor.cursor_x)
+    }
+
+    /// Write `bytes` to a file in the temporary directory and return its path
+    fn temp_file(name: &str, bytes: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("rano-test-{}-{}", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn insert_on_empty_buffer() {
+        let mut e = editor(&[]);
+        assert_eq!(lines(&e), [""]);
+        e.insert_char('h');
+        e.insert_char('i');
+        assert_eq!(lines(&e), ["hi"]);
+        assert_eq!(cursor(&e), (0, 2));
+        assert!(e.modified);
+    }
+
+    #[test]
+    fn insert_newline_splits_line() {
+        let mut e = editor(&["hello"]);
+        e.cursor_x = 2;
+        e.insert_newline();
+        assert_eq!(lines(&e), ["he", "llo"]);
+        assert_eq!(cursor(&e), (1, 0));
+    }
+
+    #[test]
+    fn delete_char_mid_line() {
+        let mut e = editor(&["abc"]);
+        e.cursor_x = 2;
+        e.delete_char();
+        assert_eq!(lines(&e), ["ac"]);
+        assert_eq!(cursor(&e), (0, 1));
+    }
+
+    #[test]
+    fn delete_char_at_line_start_joins_lines() {
+        let mut e = editor(&["foo", "bar"]);
+        e.cursor_y = 1;
+        e.delete_char();
+        assert_eq!(lines(&e), ["foobar"]);
+        assert_eq!(cursor(&e), (0, 3));
+    }
+
+    #[test]
+    fn delete_char_at_buffer_start_does_nothing() {
+        let mut e = editor(&["foo"]);
+        e.delete_char();
+        assert_eq!(lines(&e), ["foo"]);
+        assert!(!e.modified);
+    }
+
+    #[test]
+    fn move_cursor_wraps_between_lines() {
+        let mut e = editor(&["ab", "cd"]);
+        e.cursor_x = 2;
+        e.move_cursor(Motion::Right, 10, 80);
+        assert_eq!(cursor(&e), (1, 0));
+        e.move_cursor(Motion::Left, 10, 80);
+        assert_eq!(cursor(&e), (0, 2));
+    }
+
+    #[test]
+    fn move_down_reaches_last_line() {
+        let mut e = editor(&["a", "b"]);
+        e.move_cursor(Motion::Down, 10, 80);
+        assert_eq!(cursor(&e), (1, 0));
+        e.move_cursor(Motion::Down, 10, 80);
+        assert_eq!(cursor(&e), (1, 0));
+    }
+
+    #[test]
+    fn search_moves_to_next_match() {
+        let mut e = editor(&["one", "two one"]);
+        assert_eq!(e.search("one".into(), (0, 1), 10), Ok(true));
+        assert_eq!(cursor(&e), (1, 4));
+        assert_eq!(e.search("three".into(), (0, 0), 10), Ok(false));
+    }
+
+    #[test]
+    fn substitute_line_and_file() {
+        let mut e = editor(&["a a", "a"]);
+        assert_eq!(e.substitute("a".into(), "b", false, false), Ok(1));
+        assert_eq!(lines(&e), ["b a", "a"]);
+        assert_eq!(e.substitute("a".into(), "bb", true, true), Ok(2));
+        assert_eq!(lines(&e), ["b bb", "bb"]);
+        e.undo();
+        assert_eq!(lines(&e), ["b a", "a"]);
+    }
+
+    #[test]
+    fn latin1_file_round_trips() {
+        let bytes = b"caf\xe9\nna\xefve\n";
+        let path = temp_file("latin1.txt", bytes);
+        let e = Editor::open(path.clone()).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(e.encoding, Encoding::Latin1);
+        assert_eq!(lines(&e), ["café", "naïve"]);
+        assert_eq!(e.encoding.encode(&e.text()).unwrap(), bytes);
+        assert!(e.encoding.encode("€").is_err());
+    }
+
+    #[test]
+    fn bom_round_trips() {
+        let bytes = b"\xEF\xBB\xBFfirst\r\nsecond\r\n";
+        let path = temp_file("bom.txt", bytes);
+        let e = Editor::open(path.clone()).unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(e.bom);
+        assert_eq!(lines(&e), ["first", "second"]);
+        assert_eq!(e.file_bytes().unwrap(), bytes);
+        let e = editor(&["plain"]);
+        assert_eq!(e.file_bytes().unwrap(), b"plain\n");
+    }
+
+    #[test]
+    fn goto_line_and_column() {
+        let mut e = editor(&["one", "two", "three"]);
+        e.goto_line(2, Some(2), 10);
+        assert_eq!(cursor(&e), (1, 1));
+        e.goto_line(3, Some(99), 10);
+        assert_eq!(cursor(&e), (2, 5));
+        e.goto_line(99, None, 10);
+        assert_eq!(cursor(&e), (2, 0));
+    }
+
+    #[test]
+    fn line_changes_against_original() {
+        use LineChange::*;
+        let mut e = editor(&["a", "b", "c"]);
+        assert_eq!(*e.line_changes(), [Unchanged; 3]);
+        e.cursor_y = 1;
+        e.cursor_x = 1;
+        e.insert_char('x');
+        e.insert_newline();
+        e.insert_char('y');
+        assert_eq!(lines(&e), ["a", "bx", "y", "c"]);
+        assert_eq!(*e.line_changes(), [Unchanged, Modified, Added, Unchanged]);
+        // Drawing again without edits reuses the comparison
+        let changes = e.line_changes();
+        assert!(Rc::ptr_eq(&changes, &e.line_changes()));
+        e.undo();
+        e.undo();
+        e.undo();
+        assert_eq!(*e.line_changes(), [Unchanged; 3]);
+    }
+
+    #[test]
+    fn edits_around_multibyte_characters() {
+        let mut e = editor(&["héllo😀"]);
+        e.move_cursor(Motion::End, 10, 80);
+        assert_eq!(cursor(&e), (0, 6));
+        e.insert_char('!');
+        assert_eq!(lines(&e), ["héllo😀!"]);
+        e.cursor_x = 2;
+        e.delete_char();
+        assert_eq!(lines(&e), ["hllo😀!"]);
+        e.insert_char('é');
+        e.cursor_x = 6;
+        e.delete_char();
+        assert_eq!(lines(&e), ["héllo!"]);
+        e.move_cursor(Motion::Left, 10, 80);
+        e.insert_newline();
+        assert_eq!(lines(&e), ["héll", "o!"]);
+    }
+
+    #[test]
+    fn search_scrolls_match_into_view() {
+        let lines = (1..=200).map(|i| format!("line {}", i)).collect();
+        let mut e = Editor::from_lines(lines);
+        let height = 20;
+        assert_eq!(e.search("line 100".into(), (0, 0), height), Ok(true));
+        assert_eq!(e.cursor_y, 99);
+        assert!(e.scroll_y <= 99 && 99 < e.scroll_y + height);
+    }
+
+    #[test]
+    fn case_insensitive_search() {
+        let mut e = editor(&["say Hello"]);
+        e.case_sensitive = false;
+        assert_eq!(e.search("hello".into(), (0, 0), 10), Ok(true));
+        assert_eq!(cursor(&e), (0, 4));
+        e.case_sensitive = true;
+        assert_eq!(e.search("hello".into(), (0, 0), 10), Ok(false));
+    }
+
+    #[test]
+    fn crlf_file_round_trips() {
+        let bytes = b"one\r\ntwo\r\n";
+        let path = temp_file("crlf.txt", bytes);
+        let e = Editor::open(path.clone()).unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(e.line_ending == LineEnding::CrLf);
+        assert_eq!(lines(&e), ["one", "two"]);
+        assert_eq!(e.file_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn missing_final_newline_round_trips() {
+        let bytes = b"first\nlast";
+        let path = temp_file("no-newline.txt", bytes);
+        let mut e = Editor::open(path.clone()).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(e.file_bytes().unwrap(), bytes);
+        e.insert_char('x');
+        assert_eq!(e.file_bytes().unwrap(), b"xfirst\nlast");
+    }
+
+    #[test]
+    fn delete_middle_line() {
+        let mut e = editor(&["one", "two", "three"]);
+        e.cursor_y = 1;
+        e.cursor_x = 2;
+        e.delete_line();
+        assert_eq!(lines(&e), ["one", "three"]);
+        assert_eq!(cursor(&e), (1, 0));
+        assert!(e.modified);
+        e.undo();
+        assert_eq!(lines(&e), ["one", "two", "three"]);
+    }
+
+    #[test]
+    fn duplicate_line_below() {
+        let mut e = editor(&["one", "two"]);
+        e.cursor_x = 2;
+        e.duplicate_line();
+        assert_eq!(lines(&e), ["one", "one", "two"]);
+        assert_eq!(cursor(&e), (1, 2));
+        assert!(e.modified);
+    }
+
+    #[test]
+    fn failed_and_refused_saves_keep_the_buffer() {
+        let mut e = editor(&["text"]);
+        e.insert_char('x');
+        let missing = std::env::temp_dir().join("rano-test-missing-dir/file.txt");
+        assert!(!e.try_save(Some(missing.to_string_lossy().into_owned())));
+        assert!(e.status_message().unwrap().starts_with("Error saving:"));
+        assert!(e.modified);
+
+        let target = std::env::temp_dir().join(format!("rano-test-{}-ro.txt", std::process::id()));
+        e.read_only = true;
+        assert!(!e.try_save(Some(target.to_string_lossy().into_owned())));
+        assert_eq!(e.status_message(), Some("Buffer is read-only"));
+        assert!(!target.exists());
+        assert!(e.modified);
+    }
+
+    #[test]
+    fn delete_forward_mid_line_and_at_end() {
+        let mut e = editor(&["abc", "def"]);
+        e.cursor_x = 1;
+        e.delete_forward();
+        assert_eq!(lines(&e), ["ac", "def"]);
+        assert_eq!(cursor(&e), (0, 1));
+        e.cursor_x = 2;
+        e.delete_forward();
+        assert_eq!(lines(&e), ["acdef"]);
+        assert_eq!(cursor(&e), (0, 2));
+    }
+
+    #[test]
+    fn many_edits_in_a_large_buffer() {
+        let lines = (0..200_000).map(|i| format!("line {}", i)).collect();
+        let mut e = Editor::from_lines(lines);
+        // Split and rejoin lines all over the file; each edit touches one chunk only
+        for n in 0..5_000 {
+            e.cursor_y = n * 39;
+            e.cursor_x = 4;
+            e.insert_newline();
+            e.delete_char();
+        }
+        assert_eq!(e.content.len(), 200_000);
+        assert_eq!(e.content[180_000], "line 180000");
+    }
+
+    #[test]
+    fn read_error_while_loading_refuses_save_and_edits() {
+        // Reading a directory fails, like a file that goes away half way through
+        let failing = || LazyLines {
+            reader: BufReader::new(File::open(std::env::temp_dir()).unwrap()),
+            encoding: Encoding::Utf8,
+            crlf: 0,
+            lf: 1,
+            last_had_newline: true,
+        };
+        let path = temp_file("partial.txt", b"one\ntwo\nthree\n");
+        let mut e = editor(&["one"]);
+        e.filename = path.clone();
+        e.pending = Some(failing());
+        e.try_save(None);
+        assert!(e.read_only);
+        assert_eq!(fs::read(&path).unwrap(), b"one\ntwo\nthree\n");
+        fs::remove_file(&path).unwrap();
+
+        e.read_only = false;
+        e.pending = Some(failing());
+        e.insert_char('x');
+        assert!(e.read_only);
+        assert_eq!(lines(&e), ["one"]);
+        assert!(!e.modified);
+    }
+
+    #[test]
+    fn insert_newline_keeps_indentation() {
+        let mut e = editor(&["    foo"]);
+        e.cursor_x = 7;
+        e.insert_newline();
+        assert_eq!(lines(&e), ["    foo", "    "]);
+        assert_eq!(cursor(&e), (1, 4));
+    }
+
+    #[test]
+    fn overwrite_replaces_mid_line_and_appends_at_end() {
+        let mut e = editor(&["abc"]);
+        e.overwrite = true;
+        e.cursor_x = 1;
+        e.insert_char('X');
+        assert_eq!(lines(&e), ["aXc"]);
+        assert_eq!(cursor(&e), (0, 2));
+        e.cursor_x = 3;
+        e.insert_char('Y');
+        assert_eq!(lines(&e), ["aXcY"]);
+        assert_eq!(cursor(&e), (0, 4));
+    }
+
+    #[test]
+    fn strip_trailing_whitespace_cleans_saved_text() {
+        let mut e = editor(&["foo  ", "bar\t", "  "]);
+        e.cursor_x = 5;
+        e.strip_trailing_whitespace();
+        assert_eq!(e.text(), "foo\nbar\n\n");
+        assert_eq!(cursor(&e), (0, 3));
+    }
+
+    #[test]
+    fn regex_search_finds_function() {
+        let mut e = editor(&["use std::io;", "", "pub fn main() {", "}"]);
+        e.regex_search = true;
+        assert_eq!(e.search(r"\bfn\s+\w+".into(), (0, 0), 10), Ok(true));
+        assert_eq!(cursor(&e), (2, 4));
+        assert_eq!(e.current_match.map(|m| m.len), Some(7));
+        assert!(e.search("(fn".into(), (0, 0), 10).is_err());
+    }
+
+    #[test]
+    fn move_cursor_in_single_line_buffer() {
+        let mut e = editor(&["hello"]);
+        e.move_cursor(Motion::Down, 10, 80);
+        assert_eq!(cursor(&e), (0, 0));
+        e.move_cursor(Motion::End, 10, 80);
+        assert_eq!(cursor(&e), (0, 5));
+        e.move_cursor(Motion::Up, 10, 80);
+        assert_eq!(cursor(&e), (0, 5));
+    }
+
+    #[test]
+    fn move_cursor_in_empty_buffer() {
+        let mut e = editor(&[]);
+        for motion in [
+            Motion::Up,
+            Motion::Down,
+            Motion::Left,
+            Motion::Right,
+            Motion::Home,
+            Motion::End,
+            Motion::PageUp,
+            Motion::PageDown,
+        ] {
+            e.move_cursor(motion, 10, 80);
+            assert_eq!(cursor(&e), (0, 0));
+        }
+    }
+
+    #[test]
+    fn tab_moves_cursor_to_tab_stop() {
+        let mut e = editor(&["\tx"]);
+        e.cursor_x = 1;
+        assert_eq!(e.display_col(0, 1), e.tab_width);
+        assert_eq!(e.cursor_on_screen(10, 80), Some((0, e.tab_width)));
+    }
+
+    #[test]
+    fn delete_word_back_mid_line() {
+        let mut e = editor(&["foo bar baz"]);
+        e.cursor_x = 7;
+        e.delete_word_back();
+        assert_eq!(lines(&e), ["foo  baz"]);
+        assert_eq!(cursor(&e), (0, 4));
+        e.undo();
+        assert_eq!(lines(&e), ["foo bar baz"]);
+    }
+
+    #[test]
+    fn typing_replaces_single_line_selection() {
+        let mut e = editor(&["hello world"]);
+        e.selection_start = Some((0, 0));
+        e.cursor_x = 5;
+        e.insert_char('X');
+        assert_eq!(lines(&e), ["X world"]);
+        assert_eq!(cursor(&e), (0, 1));
+        e.undo();
+        assert_eq!(lines(&e), ["hello world"]);
+    }
+
+    #[test]
+    fn enter_replaces_multi_line_selection() {
+        let mut e = editor(&["one", "two", "three"]);
+        e.selection_start = Some((0, 1));
+        (e.cursor_y, e.cursor_x) = (2, 2);
+        e.insert_newline();
+        assert_eq!(lines(&e), ["o", "ree"]);
+        assert_eq!(cursor(&e), (1, 0));
+    }
+
+    #[test]
+    fn tab_inserts_tab_or_spaces() {
+        let mut e = editor(&["x"]);
+        e.use_spaces = false;
+        e.insert_tab();
+        assert_eq!(lines(&e), ["\tx"]);
+
+        let mut e = editor(&["x"]);
+        e.use_spaces = true;
+        e.insert_tab();
+        assert_eq!(lines(&e), [" ".repeat(e.tab_width) + "x"]);
+    }
+
+    #[test]
+    fn join_line_collapses_whitespace() {
+        let mut e = editor(&["foo  ", "   bar"]);
+        e.join_line();
+        assert_eq!(lines(&e), ["foo bar"]);
+        assert_eq!(cursor(&e), (0, 3));
+        e.join_line();
+        assert_eq!(lines(&e), ["foo bar"]);
+    }
+
+    #[test]
+    fn reflow_long_line_at_column_40() {
+        let text = "The quick brown fox jumps over the lazy dog while the cat \
+                    watches from a sunny window sill";
+        let mut e = editor(&[text]);
+        e.reflow(40);
+        assert_eq!(
+            lines(&e),
+            [
+                "The quick brown fox jumps over the lazy",
+                "dog while the cat watches from a sunny",
+                "window sill",
+            ]
+        );
+        assert_eq!(cursor(&e), (2, 11));
+        assert!(e.modified);
+        e.undo();
+        assert_eq!(lines(&e), [text]);
+        assert_eq!(cursor(&e), (0, 0));
+
+        let mut e = editor(&["one", "two three", "four"]);
+        (e.cursor_y, e.cursor_x) = (1, 4);
+        e.reflow(40);
+        assert_eq!(lines(&e), ["one two three four"]);
+        e.undo();
+        assert_eq!(lines(&e), ["one", "two three", "four"]);
+        assert_eq!(cursor(&e), (1, 4));
+
+        e.read_only = true;
+        e.reflow(40);
+        assert_eq!(lines(&e), ["one", "two three", "four"]);
+        assert_eq!(cursor(&e), (1, 4));
+    }
+
+    #[test]
+    fn vertical_moves_keep_goal_column() {
+        let mut e = editor(&["abcdef", "ab", "abcdef"]);
+        e.cursor_x = 5;
+        e.move_cursor(Motion::Down, 10, 80);
+        assert_eq!(cursor(&e), (1, 2));
+        e.move_cursor(Motion::Down, 10, 80);
+        assert_eq!(cursor(&e), (2, 5));
+    }
+
+    #[test]
+    fn undo_back_to_saved_state_is_unmodified() {
+        let mut e = editor(&["abc"]);
+        e.insert_char('x');
+        e.insert_newline();
+        assert!(e.modified);
+        e.undo();
+        assert!(e.modified);
+        e.undo();
+        assert_eq!(lines(&e), ["abc"]);
+        assert!(!e.modified);
+        e.redo();
+        assert!(e.modified);
+        e.undo();
+        assert!(!e.modified);
+
+        e.set_line_ending(LineEnding::CrLf);
+        e.insert_char('y');
+        e.undo();
+        assert_eq!(lines(&e), ["abc"]);
+        assert!(e.modified);
+    }
+
+    #[test]
+    fn only_spaces_and_tabs_are_trailing_whitespace() {
+        assert_eq!(trim_trailing_whitespace("a \t "), "a");
+        assert_eq!(trim_trailing_whitespace("a\u{a0}"), "a\u{a0}");
+        let mut e = editor(&["a\u{a0} \t"]);
+        e.strip_trailing_whitespace();
+        assert_eq!(lines(&e), ["a\u{a0}"]);
+    }
+}
@@ -0,0 +1,544 @@
+use std::fmt;
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use rano_text_editor::config::Config;
+
+/// Editor commands that can be bound to keys
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Quit,
+    SaveQuit,
+    QuitAll,
+    ForceQuit,
+    Save,
+    SaveAs,
+    RecentFiles,
+    FindFile,
+    Search,
+    Replace,
+    FindNext,
+    GotoLine,
+    Stats,
+    Reload,
+    DeleteLine,
+    DeleteWordBack,
+    DuplicateLine,
+    Copy,
+    Cut,
+    Paste,
+    Undo,
+    Redo,
+    PrevBuffer,
+    NextBuffer,
+    MoveLineUp,
+    MoveLineDown,
+    JoinLine,
+    Reflow,
+    WordLeft,
+    WordRight,
+    ParagraphUp,
+    ParagraphDown,
+    FileStart,
+    FileEnd,
+    ToggleOverwrite,
+    ToggleWhitespace,
+    ToggleWrap,
+    ToggleSplit,
+    SwitchPane,
+    ToggleView,
+    ClearSearch,
+    Indent,
+    Dedent,
+    ToggleComment,
+    InsertDate,
+    Increment,
+    Decrement,
+    LineEnding,
+    RecordMacro,
+    StopMacro,
+    PlayMacro,
+    SetMark,
+    GotoMark,
+    MatchBracket,
+    Help,
+    CommandLine,
+}
+
+/// Name, default keys and description of a bindable action
+pub struct ActionInfo {
+    pub action: Action,
+    /// Key used for the action in the `[keys]` section of the config file
+    pub name: &'static str,
+    pub default_keys: &'static [&'static str],
+    /// What the action does, as shown on the help screen
+    pub description: &'static str,
+}
+
+const fn info(
+    action: Action,
+    name: &'static str,
+    default_keys: &'static [&'static str],
+    description: &'static str,
+) -> ActionInfo {
+    ActionInfo {
+        action,
+        name,
+        default_keys,
+        description,
+    }
+}
+
+/// Every bindable action, in the order shown to the user
+pub const ACTIONS: &[ActionInfo] = &[
+    info(Action::Help, "help", &["f1"], "Show this help"),
+    info(
+        Action::CommandLine,
+        "command_line",
+        &["ctrl+e"],
+        "Run a command such as :w, :goto 40 or :s/old/new/",
+    ),
+    info(Action::Search, "search", &["ctrl+w"], "Search as you type"),
+    info(
+        Action::FindNext,
+        "find_next",
+        &["f3", "ctrl+n"],
+        "Find next match",
+    ),
+    info(
+        Action::ClearSearch,
+        "clear_search",
+        &["esc"],
+        "Clear search highlights",
+    ),
+    info(
+        Action::ToggleView,
+        "toggle_view",
+        &["f2"],
+        "Toggle view (read-only) mode",
+    ),
+    info(Action::Replace, "replace", &["ctrl+r"], "Replace"),
+    info(Action::GotoLine, "goto_line", &["ctrl+g"], "Go to line"),
+    info(Action::SetMark, "set_mark", &["ctrl+b"], "Set a mark"),
+    info(Action::GotoMark, "goto_mark", &["alt+b"], "Go to a mark"),
+    // Most terminals send Ctrl+] as Ctrl+5
+    info(
+        Action::MatchBracket,
+        "match_bracket",
+        &["ctrl+]", "ctrl+5"],
+        "Jump to matching bracket",
+    ),
+    info(Action::Stats, "stats", &["ctrl+t"], "Statistics"),
+    info(Action::Reload, "reload", &["ctrl+l"], "Reload from disk"),
+    info(Action::Save, "save", &["ctrl+s"], "Save"),
+    info(Action::SaveAs, "save_as", &["ctrl+o"], "Save as"),
+    info(
+        Action::RecentFiles,
+        "recent_files",
+        &["alt+o"],
+        "Open a recently used file",
+    ),
+    info(
+        Action::FindFile,
+        "find_file",
+        &["ctrl+p"],
+        "Find a file to open",
+    ),
+    info(
+        Action::Quit,
+        "quit",
+        &["ctrl+q"],
+        "Close buffer / exit after the last one",
+    ),
+    info(
+        Action::SaveQuit,
+        "save_quit",
+        &["alt+x"],
+        "Save, then close buffer / exit",
+    ),
+    info(
+        Action::ForceQuit,
+        "force_quit",
+        &["alt+q"],
+        "Discard changes, then close buffer / exit",
+    ),
+    info(
+        Action::QuitAll,
+        "quit_all",
+        &["ctrl+alt+q"],
+        "Exit, asking about every buffer with unsaved changes",
+    ),
+    info(
+        Action::PrevBuffer,
+        "prev_buffer",
+        &["alt+left"],
+        "Previous buffer",
+    ),
+    info(
+        Action::NextBuffer,
+        "next_buffer",
+        &["alt+right"],
+        "Next buffer",
+    ),
+    info(
+        Action::ToggleSplit,
+        "split",
+        &["f4"],
+        "Split view side by side",
+    ),
+    info(Action::SwitchPane, "switch_pane", &["f6"], "Switch pane"),
+    info(
+        Action::ToggleOverwrite,
+        "overwrite",
+        &["insert"],
+        "Toggle insert / overwrite mode",
+    ),
+    info(
+        Action::ToggleWhitespace,
+        "whitespace",
+        &["f5"],
+        "Show / hide whitespace",
+    ),
+    info(
+        Action::ToggleWrap,
+        "wrap",
+        &["alt+z"],
+        "Soft wrap long lines on / off",
+    ),
+    info(
+        Action::DeleteLine,
+        "delete_line",
+        &["ctrl+k"],
+        "Delete line",
+    ),
+    // Some terminals send Ctrl+Backspace as Alt+Backspace. Others send it as Ctrl+H,
+    // but so does plain Backspace on terminals using ^H, so that one is left to `[keys]`.
+    info(
+        Action::DeleteWordBack,
+        "delete_word",
+        &["ctrl+backspace", "alt+backspace"],
+        "Delete the previous word",
+    ),
+    info(
+        Action::DuplicateLine,
+        "duplicate_line",
+        &["ctrl+d"],
+        "Duplicate line",
+    ),
+    info(
+        Action::MoveLineUp,
+        "move_line_up",
+        &["alt+up"],
+        "Move line up",
+    ),
+    info(
+        Action::MoveLineDown,
+        "move_line_down",
+        &["alt+down"],
+        "Move line down",
+    ),
+    info(
+        Action::JoinLine,
+        "join_line",
+        &["ctrl+j"],
+        "Join the next line onto this one",
+    ),
+    info(
+        Action::Reflow,
+        "reflow",
+        &["alt+j"],
+        "Wrap the paragraph or selected lines",
+    ),
+    info(Action::Copy, "copy", &["ctrl+c"], "Copy selection or line"),
+    info(Action::Cut, "cut", &["ctrl+x"], "Cut selection or line"),
+    info(Action::Paste, "paste", &["ctrl+v"], "Paste"),
+    info(Action::Undo, "undo", &["ctrl+z"], "Undo"),
+    info(Action::Redo, "redo", &["ctrl+y"], "Redo"),
+    info(
+        Action::FileStart,
+        "file_start",
+        &["ctrl+home"],
+        "File start",
+    ),
+    info(Action::FileEnd, "file_end", &["ctrl+end"], "File end"),
+    info(Action::WordLeft, "word_left", &["ctrl+left"], "Word left"),
+    info(
+        Action::WordRight,
+        "word_right",
+        &["ctrl+right"],
+        "Word right",
+    ),
+    info(
+        Action::ParagraphUp,
+        "paragraph_up",
+        &["ctrl+up"],
+        "Previous blank line",
+    ),
+    info(
+        Action::ParagraphDown,
+        "paragraph_down",
+        &["ctrl+down"],
+        "Next blank line",
+    ),
+    info(
+        Action::Indent,
+        "indent",
+        &["tab"],
+        "Indent line or selected lines",
+    ),
+    info(
+        Action::Dedent,
+        "dedent",
+        &["shift+tab"],
+        "Dedent line or selected lines",
+    ),
+    // Most terminals send Ctrl+/ as Ctrl+7
+    info(
+        Action::ToggleComment,
+        "toggle_comment",
+        &["ctrl+/", "ctrl+7"],
+        "Comment / uncomment line or selected lines",
+    ),
+    info(
+        Action::InsertDate,
+        "insert_date",
+        &["alt+d"],
+        "Insert the current date and time",
+    ),
+    info(
+        Action::Increment,
+        "increment",
+        &["alt+="],
+        "Add one to the number at the cursor",
+    ),
+    info(
+        Action::Decrement,
+        "decrement",
+        &["alt+-"],
+        "Subtract one from the number at the cursor",
+    ),
+    info(
+        Action::LineEnding,
+        "line_ending",
+        &["alt+l"],
+        "Convert line endings (LF / CRLF)",
+    ),
+    info(
+        Action::RecordMacro,
+        "record_macro",
+        &["f7"],
+        "Start recording a macro",
+    ),
+    info(
+        Action::StopMacro,
+        "stop_macro",
+        &["f8"],
+        "Stop recording the macro",
+    ),
+    info(
+        Action::PlayMacro,
+        "play_macro",
+        &["f9"],
+        "Play the macro a number of times",
+    ),
+];
+
+/// Action called `name` in the config file
+pub fn action_named(name: &str) -> Option<Action> {
+    ACTIONS
+        .iter()
+        .find(|info| info.name == name)
+        .map(|info| info.action)
+}
+
+/// A key together with the modifiers held with it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    /// Build a binding from a key event, folding Shift+Tab into BackTab
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        match code {
+            KeyCode::BackTab => Self {
+                code,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            _ => Self { code, modifiers },
+        }
+    }
+
+    /// Parse a binding such as `ctrl+s`, `alt+up` or `f3`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let lower = text.trim().to_ascii_lowercase();
+        let mut parts: Vec<&str> = lower.split('+').collect();
+        // A trailing empty part means the key itself is `+`, as in `ctrl++`
+        let key = match parts.pop() {
+            Some("") if parts.last() == Some(&"") => {
+                parts.pop();
+                "+"
+            }
+            Some(key) => key,
+            None => "",
+        };
+        let mut modifiers = KeyModifiers::NONE;
+        for part in parts {
+            modifiers |= match part {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier `{}` in `{}`", part, text)),
+            };
+        }
+        let code = match key {
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "enter" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" | "ins" => KeyCode::Insert,
+            "space" => KeyCode::Char(' '),
+            _ if key.len() > 1 && key.starts_with('f') => match key[1..].parse::<u8>() {
+                Ok(n @ 1..=12) => KeyCode::F(n),
+                _ => return Err(format!("unknown key `{}` in `{}`", key, text)),
+            },
+            _ => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => return Err(format!("unknown key `{}` in `{}`", key, text)),
+                }
+            }
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    /// Write the binding the way it is shown to the user, e.g. `Ctrl+S` or `Shift+Tab`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl+"),
+            (KeyModifiers::ALT, "Alt+"),
+            (KeyModifiers::SHIFT, "Shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Up => f.write_str("Up"),
+            KeyCode::Down => f.write_str("Down"),
+            KeyCode::Left => f.write_str("Left"),
+            KeyCode::Right => f.write_str("Right"),
+            KeyCode::Home => f.write_str("Home"),
+            KeyCode::End => f.write_str("End"),
+            KeyCode::PageUp => f.write_str("PageUp"),
+            KeyCode::PageDown => f.write_str("PageDown"),
+            KeyCode::Tab => f.write_str("Tab"),
+            KeyCode::BackTab => f.write_str("Shift+Tab"),
+            KeyCode::Enter => f.write_str("Enter"),
+            KeyCode::Esc => f.write_str("Esc"),
+            KeyCode::Backspace => f.write_str("Backspace"),
+            KeyCode::Delete => f.write_str("Delete"),
+            KeyCode::Insert => f.write_str("Insert"),
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// Lookup table from keys to actions
+pub struct Keymap {
+    bindings: Vec<(KeyBinding, Action)>,
+}
+
+impl Keymap {
+    /// Build the keymap from the `[keys]` section of `config`, e.g. `quit = "ctrl+x"` or
+    /// `find_next = ["f3", "ctrl+n"]`. Actions without a valid entry keep their default
+    /// keys, and configured keys take priority over defaults. Problems are returned as
+    /// messages instead of failing.
+    pub fn from_config(config: &Config) -> (Self, Vec<String>) {
+        let mut errors = Vec::new();
+        let mut configured: Vec<(KeyBinding, Action)> = Vec::new();
+        let mut overridden = Vec::new();
+
+        for entry in config.section("keys") {
+            let Some(action) = action_named(&entry.key) else {
+                errors.push(format!(
+                    "line {}: unknown action `{}`",
+                    entry.line, entry.key
+                ));
+                continue;
+            };
+            let Some(keys) = entry.value.as_list() else {
+                errors.push(format!(
+                    "line {}: keys for `{}` must be a string or a list of strings",
+                    entry.line, entry.key
+                ));
+                continue;
+            };
+            let mut bound = false;
+            for key in keys {
+                match KeyBinding::parse(&key) {
+                    Ok(binding) => {
+                        configured.push((binding, action));
+                        bound = true;
+                    }
+                    Err(e) => errors.push(format!("line {}: {}", entry.line, e)),
+                }
+            }
+            if bound {
+                overridden.push(action);
+            }
+        }
+
+        let defaults = ACTIONS
+            .iter()
+            .filter(|info| !overridden.contains(&info.action))
+            .flat_map(|info| {
+                info.default_keys.iter().map(move |key| {
+                    let binding = KeyBinding::parse(key).expect("invalid default key binding");
+                    (binding, info.action)
+                })
+            });
+        configured.extend(defaults);
+        (
+            Self {
+                bindings: configured,
+            },
+            errors,
+        )
+    }
+
+    /// Keys bound to `action`, configured ones first
+    pub fn keys_for(&self, action: Action) -> impl Iterator<Item = KeyBinding> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |&&(_, a)| a == action)
+            .map(|&(binding, _)| binding)
+    }
+
+    /// Action bound to a key event, if any
+    pub fn action(&self, code: KeyCode, modifiers: KeyModifiers) -> Option<Action> {
+        let pressed = KeyBinding::new(code, modifiers);
+        self.bindings
+            .iter()
+            .find(|(binding, _)| *binding == pressed)
+            .map(|&(_, action)| action)
+    }
+}
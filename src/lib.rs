@@ -0,0 +1,13 @@
+//! Editing core of rano: the text buffer, cursor movement, editing, search, undo and
+//! file handling. Nothing here touches the terminal; the `rano` binary is the front end.
+
+pub mod buffer;
+pub mod config;
+pub mod datetime;
+pub mod editor;
+pub mod positions;
+pub mod recent;
+pub mod regex;
+pub mod syntax;
+
+pub use editor::{Editor, Motion};
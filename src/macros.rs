@@ -0,0 +1,85 @@
+use std::{cell::RefCell, collections::VecDeque, io, time::Duration};
+
+use crossterm::event::{self, Event};
+
+/// Keyboard macro state, shared by every place that reads input so that keys typed into
+/// prompts are recorded and replayed along with the rest
+#[derive(Default)]
+struct Macros {
+    /// Events recorded so far, while recording
+    recording: Option<Vec<Event>>,
+    /// The last finished recording
+    recorded: Vec<Event>,
+    /// Events of a replay still to be handed out, before any terminal input
+    queued: VecDeque<Event>,
+}
+
+thread_local! {
+    static MACROS: RefCell<Macros> = RefCell::default();
+}
+
+/// Next input event, from a macro being replayed or else from the terminal.
+/// Key presses and pastes are recorded while recording.
+pub fn read() -> io::Result<Event> {
+    if let Some(event) = MACROS.with_borrow_mut(|m| m.queued.pop_front()) {
+        return Ok(event);
+    }
+    let event = event::read()?;
+    if matches!(event, Event::Key(_) | Event::Paste(_)) {
+        MACROS.with_borrow_mut(|m| {
+            if let Some(recording) = &mut m.recording {
+                recording.push(event.clone());
+            }
+        });
+    }
+    Ok(event)
+}
+
+/// Whether an event is ready, waiting up to `timeout` for terminal input.
+/// A replay in progress never waits, so timers can't interleave with its keys.
+pub fn poll(timeout: Duration) -> io::Result<bool> {
+    if MACROS.with_borrow(|m| !m.queued.is_empty()) {
+        return Ok(true);
+    }
+    event::poll(timeout)
+}
+
+/// Start recording a new macro, discarding any recording in progress
+pub fn start_recording() {
+    MACROS.with_borrow_mut(|m| m.recording = Some(Vec::new()));
+}
+
+/// Number of events recorded so far, zero when not recording
+pub fn recorded_len() -> usize {
+    MACROS.with_borrow(|m| m.recording.as_ref().map_or(0, Vec::len))
+}
+
+/// Finish recording, keeping the first `len` events. Those after them are the key that
+/// stopped it, and anything typed into the command line on the way.
+/// Returns the number of events recorded, or `None` when not recording.
+pub fn stop_recording(len: usize) -> Option<usize> {
+    MACROS.with_borrow_mut(|m| {
+        let mut events = m.recording.take()?;
+        events.truncate(len);
+        m.recorded = events;
+        Some(m.recorded.len())
+    })
+}
+
+pub fn is_recording() -> bool {
+    MACROS.with_borrow(|m| m.recording.is_some())
+}
+
+/// Queue the recorded macro to be replayed `times` times. Returns `false` when nothing
+/// has been recorded.
+pub fn play(times: usize) -> bool {
+    MACROS.with_borrow_mut(|m| {
+        if m.recorded.is_empty() {
+            return false;
+        }
+        for _ in 0..times {
+            m.queued.extend(m.recorded.iter().cloned());
+        }
+        true
+    })
+}
@@ -1,185 +1,300 @@
+mod clipboard;
+mod command;
+mod keymap;
+mod macros;
+mod theme;
+
 use std::{
-    fs::{self, File},
-    io::{self, Write},
+    env, fs,
+    io::{self, BufWriter, IsTerminal, Read, Write},
+    panic,
+    path::Path,
+    process,
+    rc::Rc,
     time::{Duration, Instant},
 };
 
+use clipboard::Clipboard;
+use command::Command;
 use crossterm::{
     cursor::{Hide, Show},
-    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    event::{
+        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    },
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
+use keymap::{ACTIONS, Action, Keymap};
+use rano_text_editor::{
+    config::{Config, Value},
+    datetime::DateTime,
+    editor::{
+        DEFAULT_FILENAME, Editor, FileStatus, LOAD_AHEAD, LineChange, LineEnding, Motion, Position,
+        char_width, trim_trailing_whitespace,
+    },
+    positions, recent,
+    syntax::{self, Token},
+};
 use ratatui::{
-    Terminal,
+    Frame, Terminal,
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
+    style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
 };
+use theme::Theme;
 
-/// Represents the current state of the text editor
-struct Editor {
-    filename: String,
-    content: Vec<String>,
-    cursor_x: usize,
-    cursor_y: usize,
-    scroll_y: usize,
-    modified: bool,
-    search_query: Option<String>,
-}
-
-impl Editor {
-    /// Load file or start with an empty buffer
-    fn open(filename: String) -> io::Result<Self> {
-        let content = fs::read_to_string(&filename)
-            .unwrap_or_default()
-            .lines()
-            .map(|l| l.to_string())
-            .collect::<Vec<_>>();
+/// Lines scrolled per mouse wheel step
+const WHEEL_LINES: isize = 3;
 
-        Ok(Self {
-            filename,
-            content,
-            cursor_x: 0,
-            cursor_y: 0,
-            scroll_y: 0,
-            modified: false,
-            search_query: None,
-        })
-    }
+/// Where the interface is drawn: stdout, or stderr when stdout receives the buffer
+type Output = Box<dyn Write>;
 
-    /// Save file to disk (optionally under a new name)
-    fn save(&mut self, new_name: Option<String>) -> io::Result<()> {
-        if let Some(name) = new_name {
-            self.filename = name;
-        }
-        let mut file = File::create(&self.filename)?;
-        for line in &self.content {
-            writeln!(file, "{}", line)?;
+/// Narrowest the filename is squeezed to before the status bar drops it
+const MIN_FILENAME_WIDTH: usize = 8;
+
+/// Idle time after which modified buffers are written to their swap files
+const SWAP_DELAY: Duration = Duration::from_secs(1);
+
+/// How long the cursor stays shown or hidden while blinking, unless `blink_interval` is set
+const DEFAULT_BLINK_INTERVAL: u64 = 500;
+
+/// Timestamp inserted by Alt+D unless `date_format` is configured
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Column Alt+J wraps text at unless `reflow_width` is configured
+const DEFAULT_REFLOW_WIDTH: usize = 72;
+
+/// Smallest terminal the editor is drawn in; anything smaller shows a notice instead
+const MIN_TERMINAL_WIDTH: u16 = 20;
+const MIN_TERMINAL_HEIGHT: u16 = 5;
+
+/// How deep into subdirectories the file finder looks
+const FINDER_MAX_DEPTH: usize = 8;
+
+/// Most files the file finder lists, so huge trees stay responsive
+const FINDER_MAX_FILES: usize = 10_000;
+
+/// The open buffers and the one currently being edited
+struct Workspace {
+    buffers: Vec<Editor>,
+    active: usize,
+    /// Buffer shown in the unfocused pane when the view is split
+    split: Option<usize>,
+    /// Whether the focused pane is the right-hand one
+    focus_right: bool,
+    /// Hint shown in the middle of an empty buffer
+    placeholder: String,
+    theme: Theme,
+}
+
+impl Workspace {
+    fn new(buffers: Vec<Editor>) -> Self {
+        Self {
+            buffers,
+            active: 0,
+            split: None,
+            focus_right: false,
+            placeholder: String::new(),
+            theme: Theme::default(),
         }
-        self.modified = false;
-        Ok(())
     }
 
-    /// Insert a character at the current cursor position
-    fn insert_char(&mut self, ch: char) {
-        if self.cursor_y >= self.content.len() {
-            self.content.push(String::new());
-        }
-        self.content[self.cursor_y].insert(self.cursor_x, ch);
-        self.cursor_x += 1;
-        self.modified = true;
+    fn editor(&self) -> &Editor {
+        &self.buffers[self.active]
+    }
+
+    fn editor_mut(&mut self) -> &mut Editor {
+        &mut self.buffers[self.active]
+    }
+
+    /// Rows taken by the tab bar, which is only shown with more than one buffer
+    fn tab_bar_height(&self) -> u16 {
+        u16::from(self.buffers.len() > 1)
     }
 
-    /// Handle line breaks (Enter key)
-    fn insert_newline(&mut self) {
-        if self.cursor_y >= self.content.len() {
-            self.content.push(String::new());
+    /// Divide the terminal into the tab bar and one pane per visible buffer.
+    /// Each pane is returned with its buffer index and whether it has focus.
+    fn layout(&self, area: Rect) -> (Rect, Vec<(usize, Rect, bool)>) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(self.tab_bar_height()),
+                Constraint::Min(1),
+            ])
+            .split(area);
+        let Some(other) = self.split else {
+            return (rows[0], vec![(self.active, rows[1], true)]);
+        };
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+            .split(rows[1]);
+        let (left, right) = if self.focus_right {
+            (other, self.active)
         } else {
-            let rest = self.content[self.cursor_y].split_off(self.cursor_x);
-            self.content.insert(self.cursor_y + 1, rest);
+            (self.active, other)
+        };
+        let panes = vec![
+            (left, columns[0], !self.focus_right),
+            (right, columns[1], self.focus_right),
+        ];
+        (rows[0], panes)
+    }
+
+    /// Area of the focused pane
+    fn focused_area(&self, area: Rect) -> Rect {
+        let (_, panes) = self.layout(area);
+        panes
+            .into_iter()
+            .find_map(|(_, rect, focused)| focused.then_some(rect))
+            .unwrap_or(area)
+    }
+
+    /// Text rows available to the active buffer when the terminal covers `area`
+    fn visible_height(&self, area: Rect) -> usize {
+        self.focused_area(area).height.saturating_sub(3) as usize
+    }
+
+    /// Text columns available to the active buffer when the terminal covers `area`
+    fn visible_width(&self, area: Rect) -> usize {
+        (self.focused_area(area).width.saturating_sub(2) as usize)
+            .saturating_sub(self.editor().gutter_width())
+    }
+
+    /// Scroll every visible buffer so its cursor stays inside its pane when the terminal
+    /// covers `area`
+    fn keep_cursors_visible(&mut self, area: Rect) {
+        let (_, panes) = self.layout(area);
+        for (index, pane, _) in panes {
+            let editor = &mut self.buffers[index];
+            let height = pane.height.saturating_sub(3) as usize;
+            let width =
+                (pane.width.saturating_sub(2) as usize).saturating_sub(editor.gutter_width());
+            editor.scroll_y_to_cursor(height, width);
+            editor.scroll_x_to_cursor(width);
         }
-        self.cursor_y += 1;
-        self.cursor_x = 0;
-        self.modified = true;
     }
 
-    /// Delete a character (Backspace)
-    fn delete_char(&mut self) {
-        if self.cursor_y < self.content.len() && self.cursor_x > 0 {
-            self.content[self.cursor_y].remove(self.cursor_x - 1);
-            self.cursor_x -= 1;
-            self.modified = true;
-        } else if self.cursor_y > 0 {
-            let current = self.content.remove(self.cursor_y);
-            self.cursor_y -= 1;
-            self.cursor_x = self.content[self.cursor_y].len();
-            self.content[self.cursor_y].push_str(&current);
-            self.modified = true;
+    /// Split the view in two, showing the next buffer beside the active one, or unsplit it
+    fn toggle_split(&mut self) {
+        self.split = match self.split {
+            Some(_) => None,
+            None => Some((self.active + 1) % self.buffers.len()),
+        };
+        self.focus_right = false;
+    }
+
+    /// Move focus to the other pane when the view is split
+    fn switch_pane(&mut self) {
+        if let Some(other) = self.split {
+            self.split = Some(self.active);
+            self.active = other;
+            self.focus_right = !self.focus_right;
         }
     }
 
-    /// Move the cursor (with basic bounds and scrolling)
-    fn move_cursor(&mut self, code: KeyCode, visible_height: usize) {
-        let len = self.current_line().map(|s| s.len()).unwrap_or(0);
-        match code {
-            KeyCode::Up => {
-                if self.cursor_y > 0 {
-                    self.cursor_y -= 1;
-                    if self.cursor_y < self.scroll_y {
-                        self.scroll_y -= 1;
-                    }
-                    self.cursor_x = self.cursor_x.min(len);
-                }
-            }
-            KeyCode::Down => {
-                if self.cursor_y + 1 < self.content.len() {
-                    self.cursor_y += 1;
-                    if self.cursor_y >= self.scroll_y + visible_height {
-                        self.scroll_y += 1;
-                    }
-                    self.cursor_x = self.cursor_x.min(len);
-                }
-            }
-            KeyCode::Left => {
-                if self.cursor_x > 0 {
-                    self.cursor_x -= 1;
-                } else if self.cursor_y > 0 {
-                    self.cursor_y -= 1;
-                    self.cursor_x = len;
-                }
-            }
-            KeyCode::Right => {
-                if self.cursor_x < len {
-                    self.cursor_x += 1;
-                } else if self.cursor_y + 1 < self.content.len() {
-                    self.cursor_y += 1;
-                    self.cursor_x = 0;
-                }
-            }
-            _ => {}
+    fn next(&mut self) {
+        self.active = (self.active + 1) % self.buffers.len();
+    }
+
+    fn prev(&mut self) {
+        self.active = (self.active + self.buffers.len() - 1) % self.buffers.len();
+    }
+
+    /// Switch to buffer `index` if it exists
+    fn select(&mut self, index: usize) {
+        if index < self.buffers.len() {
+            self.active = index;
         }
     }
 
-    fn current_line(&self) -> Option<&String> {
-        self.content.get(self.cursor_y)
+    /// Drop the active buffer and switch to its neighbour; the last buffer is never removed.
+    /// A split view collapses to the other pane's buffer.
+    fn close_active(&mut self) {
+        if self.buffers.len() <= 1 {
+            return;
+        }
+        let closed = self.active;
+        let mut editor = self.buffers.remove(closed);
+        editor.store_position();
+        editor.remove_swap();
+        let shift = |i: usize| if i > closed { i - 1 } else { i };
+        match self.split.take() {
+            Some(other) if other != closed => self.active = shift(other),
+            _ => self.active = closed.min(self.buffers.len() - 1),
+        }
+        self.focus_right = false;
     }
+}
 
-    /// Search for a term in the file and move cursor
-    fn search(&mut self, query: String) {
-        self.search_query = Some(query.clone());
-        if let Some((y, _)) = self
-            .content
-            .iter()
-            .enumerate()
-            .find(|(_, line)| line.contains(&query))
-        {
-            self.cursor_y = y;
-            self.cursor_x = self.content[y].find(&query).unwrap_or(0);
+/// Place the cursor on a left click and scroll on the mouse wheel.
+/// Clicking the unfocused pane of a split view focuses it.
+fn handle_mouse(workspace: &mut Workspace, mouse: MouseEvent, size: Rect) {
+    let (_, panes) = workspace.layout(size);
+    let Some(&(index, area, focused)) = panes.iter().find(|(_, area, _)| {
+        (area.x..area.x + area.width).contains(&mouse.column)
+            && (area.y..area.y + area.height).contains(&mouse.row)
+    }) else {
+        return;
+    };
+    // Text rows sit inside the border, above the status bar
+    let visible_height = area.height.saturating_sub(3) as usize;
+    let editor = &mut workspace.buffers[index];
+    match mouse.kind {
+        MouseEventKind::ScrollUp => editor.scroll_by(-WHEEL_LINES, visible_height),
+        MouseEventKind::ScrollDown => editor.scroll_by(WHEEL_LINES, visible_height),
+        MouseEventKind::Down(MouseButton::Left) => {
+            let row = mouse.row.saturating_sub(area.y + 1) as usize;
+            if mouse.row <= area.y || row >= visible_height {
+                return;
+            }
+            let gutter_width = editor.gutter_width();
+            let col =
+                (mouse.column.saturating_sub(area.x + 1) as usize).saturating_sub(gutter_width);
+            let width = (area.width.saturating_sub(2) as usize).saturating_sub(gutter_width);
+            editor.clear_selection();
+            (editor.cursor_y, editor.cursor_x) = editor.position_at(row, col, width);
+            if !focused {
+                workspace.switch_pane();
+            }
         }
+        _ => {}
     }
 }
 
+/// Cursor movement for a key, for keys that can extend a selection when combined with Shift
+fn motion_for(code: KeyCode) -> Option<Motion> {
+    Some(match code {
+        KeyCode::Up => Motion::Up,
+        KeyCode::Down => Motion::Down,
+        KeyCode::Left => Motion::Left,
+        KeyCode::Right => Motion::Right,
+        KeyCode::Home => Motion::Home,
+        KeyCode::End => Motion::End,
+        KeyCode::PageUp => Motion::PageUp,
+        KeyCode::PageDown => Motion::PageDown,
+        _ => return None,
+    })
+}
+
 /// Prompt user for input text (used for save or search dialogs)
 fn prompt_input(
-    term: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    theme: &Theme,
     message: &str,
 ) -> io::Result<String> {
     let mut input = String::new();
     loop {
         term.draw(|f| {
             let area = centered_rect(60, 20, f.size());
-            let block = Block::default()
-                .title(message)
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Cyan));
-            let paragraph = Paragraph::new(input.clone()).block(block);
+            let paragraph = Paragraph::new(input.clone()).block(popup_block(message, theme));
             f.render_widget(paragraph, area);
         })?;
 
-        if let Event::Key(KeyEvent { code, .. }) = event::read()? {
+        if let Event::Key(KeyEvent { code, .. }) = macros::read()? {
             match code {
                 KeyCode::Enter => break,
                 KeyCode::Char(c) => input.push(c),
@@ -194,6 +309,1086 @@ fn prompt_input(
     Ok(input)
 }
 
+/// Ask for a filename and save under it, confirming before overwriting another file
+fn save_as(
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    theme: &Theme,
+    editor: &mut Editor,
+) -> io::Result<()> {
+    let new_name = prompt_input(term, theme, "Save as:")?;
+    if new_name.is_empty() {
+        return Ok(());
+    }
+    if new_name != editor.filename && Path::new(&new_name).exists() {
+        let answer = prompt_input(term, theme, "File exists, overwrite? (y/n)")?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            editor.set_status("Save cancelled");
+            return Ok(());
+        }
+    }
+    editor.try_save(Some(new_name));
+    Ok(())
+}
+
+/// Before exiting, offer to save or discard every buffer with unsaved changes (leaving out
+/// the active one unless `include_active`). Returns whether it is fine to exit; on cancel,
+/// or when a buffer fails to save, the first buffer still unsaved becomes active.
+fn confirm_quit_all(
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    workspace: &mut Workspace,
+    include_active: bool,
+) -> io::Result<bool> {
+    let unsaved: Vec<usize> = (0..workspace.buffers.len())
+        .filter(|&i| workspace.buffers[i].modified && (include_active || i != workspace.active))
+        .collect();
+    let Some(&first) = unsaved.first() else {
+        return Ok(true);
+    };
+    let names: Vec<&str> = unsaved
+        .iter()
+        .map(|&i| workspace.buffers[i].filename.as_str())
+        .collect();
+    let message = format!(
+        "Unsaved changes in {}. (s)ave all, (d)iscard all or (c)ancel?",
+        names.join(", ")
+    );
+    let theme = workspace.theme;
+    let answer = prompt_input(term, &theme, &message)?;
+    match answer.trim().to_ascii_lowercase().as_str() {
+        "s" => {
+            for i in unsaved {
+                if !save_or_ask(term, &theme, &mut workspace.buffers[i])? {
+                    workspace.select(i);
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        "d" => Ok(true),
+        _ => {
+            workspace.select(first);
+            Ok(false)
+        }
+    }
+}
+
+/// Ask for a command and carry it out. Returns the action the command stands for, or
+/// `CommandLine` itself once the command has been handled here.
+fn run_command_line(
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    theme: &Theme,
+    editor: &mut Editor,
+    visible_height: usize,
+) -> io::Result<Option<Action>> {
+    let input = prompt_input(term, theme, ":")?;
+    if input.trim().is_empty() {
+        return Ok(Some(Action::CommandLine));
+    }
+    match command::parse(&input) {
+        Ok(Command::Action(action)) => return Ok(Some(action)),
+        Ok(Command::Goto(line, column)) => editor.goto_line(line, column, visible_height),
+        Ok(Command::Set(setting, on)) => setting.apply(editor, on),
+        Ok(Command::Substitute {
+            query,
+            replacement,
+            whole_file,
+            global,
+        }) => match editor.substitute(query, &replacement, whole_file, global) {
+            Ok(replaced) => editor.set_status(format!("Replaced {} occurrence(s)", replaced)),
+            Err(e) => editor.set_status(format!("Invalid regex: {}", e)),
+        },
+        Err(e) => editor.set_status(e),
+    }
+    Ok(Some(Action::CommandLine))
+}
+
+/// Save under the current name, or ask for one if the buffer has none yet.
+/// Returns whether the buffer ended up saved.
+fn save_or_ask(
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    theme: &Theme,
+    editor: &mut Editor,
+) -> io::Result<bool> {
+    if editor.needs_name() {
+        save_as(term, theme, editor)?;
+        return Ok(!editor.modified);
+    }
+    save_checked(term, theme, editor)
+}
+
+/// Save to the current file, asking first if it was changed on disk since it was loaded.
+/// Returns whether the buffer ended up saved.
+fn save_checked(
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    theme: &Theme,
+    editor: &mut Editor,
+) -> io::Result<bool> {
+    if editor.changed_on_disk() {
+        let answer = prompt_input(
+            term,
+            theme,
+            "File changed on disk. (o)verwrite, (r)eload or (c)ancel?",
+        )?;
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "o" => {}
+            "r" => {
+                if let Err(e) = editor.reload() {
+                    editor.set_status(format!("Error reloading: {}", e));
+                }
+                return Ok(false);
+            }
+            _ => {
+                editor.set_status("Save cancelled");
+                return Ok(false);
+            }
+        }
+    }
+    Ok(editor.try_save(None))
+}
+
+/// Show a popup with `lines` over the editor until any key is pressed
+fn show_popup(
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    workspace: &Workspace,
+    title: &str,
+    lines: Vec<String>,
+) -> io::Result<()> {
+    let text = lines.into_iter().map(Line::from).collect::<Vec<_>>();
+    term.draw(|f| {
+        draw_workspace(f, workspace, false);
+        let area = centered_rect(60, 50, f.size());
+        let block = popup_block(title, &workspace.theme);
+        f.render_widget(Clear, area);
+        f.render_widget(Paragraph::new(text).block(block), area);
+    })?;
+    while !matches!(macros::read()?, Event::Key(_)) {}
+    Ok(())
+}
+
+/// Let the user choose one of `items`. Typing filters the list by fuzzy match, Up/Down
+/// move the selection and Enter picks it, or picks the typed text when nothing matches;
+/// Esc cancels.
+fn pick_from_list(
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    workspace: &Workspace,
+    title: &str,
+    items: &[String],
+) -> io::Result<Option<String>> {
+    let theme = &workspace.theme;
+    let mut query = String::new();
+    let mut shown: Vec<&String> = items.iter().collect();
+    let mut selected: usize = 0;
+    let mut scroll = 0;
+    loop {
+        let mut page = 0;
+        term.draw(|f| {
+            draw_workspace(f, workspace, false);
+            let area = centered_rect(60, 50, f.size());
+            // One row goes to the query
+            page = area.height.saturating_sub(3).max(1) as usize;
+            // Keep the selected entry on screen
+            scroll = scroll.clamp(selected.saturating_sub(page - 1), selected);
+            let width = area.width.saturating_sub(2) as usize;
+            let mut lines = vec![Line::from(format!("> {}", query))];
+            lines.extend(shown.iter().enumerate().skip(scroll).map(|(i, item)| {
+                let style = if i == selected {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                Line::from(Span::styled(truncate_path(item, width), style))
+            }));
+            f.render_widget(Clear, area);
+            f.render_widget(Paragraph::new(lines).block(popup_block(title, theme)), area);
+        })?;
+        let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = macros::read()?
+        else {
+            continue;
+        };
+        let last = shown.len().saturating_sub(1);
+        match code {
+            KeyCode::Up => selected = selected.saturating_sub(1),
+            KeyCode::Down => selected = (selected + 1).min(last),
+            KeyCode::PageUp => selected = selected.saturating_sub(page),
+            KeyCode::PageDown => selected = (selected + page).min(last),
+            KeyCode::Enter => {
+                return Ok(match shown.get(selected) {
+                    Some(item) => Some(item.to_string()),
+                    None if !query.is_empty() => Some(query),
+                    None => None,
+                });
+            }
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Backspace | KeyCode::Char(_)
+                if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                match code {
+                    KeyCode::Char(c) => query.push(c),
+                    _ => {
+                        query.pop();
+                    }
+                }
+                if query.is_empty() {
+                    shown = items.iter().collect();
+                    (selected, scroll) = (0, 0);
+                    continue;
+                }
+                let mut ranked = items
+                    .iter()
+                    .filter_map(|item| Some((fuzzy_rank(item, &query)?, item)))
+                    .collect::<Vec<_>>();
+                // Stable, so equally good matches keep their order
+                ranked.sort_by_key(|&(rank, _)| rank);
+                shown = ranked.into_iter().map(|(_, item)| item).collect();
+                (selected, scroll) = (0, 0);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// How well `item` matches `query`, whose characters have to appear in it in order,
+/// ignoring case. Lower is better: substring matches first, then matches with the
+/// characters closer together, then shorter items.
+fn fuzzy_rank(item: &str, query: &str) -> Option<(bool, usize, usize)> {
+    let item = item.to_lowercase();
+    let query = query.to_lowercase();
+    let chars: Vec<char> = item.chars().collect();
+    let (mut pos, mut first) = (0, None);
+    for q in query.chars() {
+        let found = pos + chars[pos..].iter().position(|&c| c == q)?;
+        first.get_or_insert(found);
+        pos = found + 1;
+    }
+    let span = pos - first.unwrap_or(0);
+    Some((!item.contains(&query), span, chars.len()))
+}
+
+/// Files under `dir` as paths relative to it, skipping hidden files and directories.
+/// Symbolic links to directories are not followed, and the search stops after
+/// `FINDER_MAX_DEPTH` levels or `FINDER_MAX_FILES` files.
+fn list_files(dir: &Path) -> Vec<String> {
+    fn walk(dir: &Path, prefix: &str, depth: usize, files: &mut Vec<String>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries = entries.flatten().collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            if files.len() >= FINDER_MAX_FILES {
+                return;
+            }
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+            let path = format!("{}{}", prefix, name);
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                if depth < FINDER_MAX_DEPTH {
+                    walk(&entry.path(), &format!("{}/", path), depth + 1, files);
+                }
+            } else if entry.path().is_file() {
+                files.push(path);
+            }
+        }
+    }
+    let mut files = Vec::new();
+    walk(dir, "", 1, &mut files);
+    files
+}
+
+/// Open `filename` in a new buffer set up by `configure` and switch to it, or switch to
+/// the buffer already showing it. An unnamed, unmodified active buffer is replaced.
+fn open_buffer(
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    workspace: &mut Workspace,
+    filename: &str,
+    configure: &dyn Fn(&mut Editor),
+    visible_height: usize,
+) -> io::Result<()> {
+    let same_file = |editor: &Editor| {
+        fs::canonicalize(&editor.filename).ok() == fs::canonicalize(filename).ok()
+    };
+    if let Some(index) = workspace.buffers.iter().position(same_file) {
+        workspace.select(index);
+        return Ok(());
+    }
+    let mut editor = match Editor::open(filename.to_string()) {
+        Ok(editor) => editor,
+        Err(e) => {
+            workspace
+                .editor_mut()
+                .set_status(format!("Error opening: {}", e));
+            return Ok(());
+        }
+    };
+    configure(&mut editor);
+    if editor.has_swap() {
+        let answer = prompt_input(
+            term,
+            &workspace.theme,
+            &format!("Recover unsaved changes to {}? (y/n)", editor.filename),
+        )?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            editor.remove_swap();
+        } else if let Err(e) = editor.recover_swap() {
+            editor.set_status(format!("Error reading swap file: {}", e));
+        }
+    }
+    if editor.remember_position
+        && let Some(position) = positions::load(&editor.filename)
+    {
+        editor.restore_position(position, visible_height);
+    }
+    let _ = recent::add(&editor.filename);
+    let current = workspace.editor();
+    if current.needs_name() && !current.modified {
+        *workspace.editor_mut() = editor;
+    } else {
+        workspace.buffers.push(editor);
+        workspace.active = workspace.buffers.len() - 1;
+    }
+    Ok(())
+}
+
+/// Show every action with the keys bound to it. The list comes from the keymap, so it
+/// follows the configured bindings. Up/Down and PageUp/PageDown scroll; any other key closes it.
+fn show_help(
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    workspace: &Workspace,
+    keymap: &Keymap,
+) -> io::Result<()> {
+    let entries = ACTIONS
+        .iter()
+        .map(|info| {
+            let keys = keymap
+                .keys_for(info.action)
+                .map(|key| key.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            (keys, info.description)
+        })
+        .collect::<Vec<_>>();
+    let key_width = entries
+        .iter()
+        .map(|(keys, _)| keys.chars().count())
+        .max()
+        .unwrap_or(0);
+    let lines = entries
+        .into_iter()
+        .map(|(keys, description)| format!("{:<key_width$}  {}", keys, description))
+        .collect::<Vec<_>>();
+    let theme = &workspace.theme;
+    let mut scroll = 0;
+    loop {
+        let mut page = 0;
+        term.draw(|f| {
+            draw_workspace(f, workspace, false);
+            let area = centered_rect(70, 80, f.size());
+            page = area.height.saturating_sub(2) as usize;
+            scroll = scroll.min(lines.len().saturating_sub(page));
+            let text = lines[scroll..]
+                .iter()
+                .map(|line| Line::from(line.as_str()))
+                .collect::<Vec<_>>();
+            let block = popup_block("Help - Up/Down to scroll, any other key to close", theme);
+            f.render_widget(Clear, area);
+            f.render_widget(Paragraph::new(text).block(block), area);
+        })?;
+        if let Event::Key(KeyEvent { code, .. }) = macros::read()? {
+            match code {
+                KeyCode::Up => scroll = scroll.saturating_sub(1),
+                KeyCode::Down => scroll += 1,
+                KeyCode::PageUp => scroll = scroll.saturating_sub(page),
+                KeyCode::PageDown => scroll += page,
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Prompt for a search query, jumping to the first match after the cursor as it is typed.
+/// Enter keeps the cursor on the match; Esc puts it back and clears the search.
+/// Alt+C toggles case sensitivity and Alt+R regular expressions while typing.
+fn prompt_search(
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    workspace: &mut Workspace,
+    visible_height: usize,
+) -> io::Result<()> {
+    let editor = workspace.editor_mut();
+    let origin = (editor.cursor_y, editor.cursor_x);
+    let scroll = (editor.scroll_y, editor.scroll_x);
+    let mut input = String::new();
+    // Why the current query has no match, shown in the prompt title
+    let mut problem: Option<String> = None;
+    loop {
+        let editor = workspace.editor();
+        let title = format!(
+            "Search for: [{}] [{}] (Alt+C case, Alt+R regex){}",
+            if editor.case_sensitive { "Aa" } else { "aa" },
+            if editor.regex_search { ".*" } else { "ab" },
+            problem
+                .as_deref()
+                .map_or(String::new(), |p| format!(" - {}", p))
+        );
+        term.draw(|f| {
+            draw_workspace(f, workspace, true);
+            let area = bottom_rect(60, 3, f.size());
+            let block = popup_block(&title, &workspace.theme);
+            f.render_widget(Clear, area);
+            f.render_widget(Paragraph::new(input.clone()).block(block), area);
+        })?;
+
+        let editor = workspace.editor_mut();
+        let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = macros::read()?
+        else {
+            continue;
+        };
+        match (code, modifiers) {
+            (KeyCode::Char('c'), KeyModifiers::ALT) => {
+                editor.case_sensitive = !editor.case_sensitive;
+            }
+            (KeyCode::Char('r'), KeyModifiers::ALT) => {
+                editor.regex_search = !editor.regex_search;
+            }
+            (KeyCode::Enter, _) => {
+                if input.is_empty() {
+                    editor.clear_search();
+                } else if let Some(problem) = problem {
+                    editor.set_status(capitalize(&problem));
+                }
+                return Ok(());
+            }
+            (KeyCode::Char(c), _) => input.push(c),
+            (KeyCode::Backspace, _) => {
+                input.pop();
+            }
+            (KeyCode::Esc, _) => {
+                editor.clear_search();
+                (editor.cursor_y, editor.cursor_x) = origin;
+                (editor.scroll_y, editor.scroll_x) = scroll;
+                return Ok(());
+            }
+            _ => continue,
+        }
+
+        // Search again from where the cursor started for the updated query
+        (editor.cursor_y, editor.cursor_x) = origin;
+        (editor.scroll_y, editor.scroll_x) = scroll;
+        problem = if input.is_empty() {
+            editor.clear_search();
+            None
+        } else {
+            match editor.search(input.clone(), origin, visible_height) {
+                Ok(true) => None,
+                Ok(false) => Some("no matches".into()),
+                Err(e) => Some(format!("invalid regex: {}", e)),
+            }
+        };
+        editor.update_syntax(editor.scroll_y + visible_height);
+    }
+}
+
+/// Show `message` in the status bar and read a mark name, a letter or digit.
+/// Any other key cancels.
+fn read_mark_name(
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    workspace: &mut Workspace,
+    message: &str,
+) -> io::Result<Option<char>> {
+    workspace.editor_mut().set_status(message);
+    term.draw(|f| draw_workspace(f, workspace, true))?;
+    let name = loop {
+        match macros::read()? {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                ..
+            }) if c.is_ascii_alphanumeric() => break Some(c),
+            Event::Key(_) => break None,
+            _ => {}
+        }
+    };
+    workspace.editor_mut().status_message = None;
+    Ok(name)
+}
+
+/// Walk through every match of `query`, asking whether to replace each one
+fn replace_interactive(
+    term: &mut Terminal<CrosstermBackend<Output>>,
+    workspace: &mut Workspace,
+    query: String,
+    replacement: &str,
+    visible_height: usize,
+) -> io::Result<()> {
+    let editor = workspace.editor_mut();
+    editor.load_all();
+    if let Err(e) = editor.set_search_query(query) {
+        editor.set_status(format!("Invalid regex: {}", e));
+        return Ok(());
+    }
+    editor.end_undo_step();
+    let mut replace_all = false;
+    let mut replaced = 0;
+    let (mut y, mut x) = (editor.cursor_y, editor.cursor_x);
+    // Scan from the cursor to the end, then once more from the top up to where we started
+    let mut wrapped = false;
+    let origin = (y, x);
+
+    loop {
+        let editor = workspace.editor_mut();
+        let m = match editor.find_forward(y, x) {
+            Some(m) if !wrapped || (m.y, m.x) < origin => m,
+            Some(_) => break,
+            None if !wrapped => {
+                wrapped = true;
+                (y, x) = (0, 0);
+                continue;
+            }
+            None => break,
+        };
+
+        let answer = if replace_all {
+            'a'
+        } else {
+            editor.jump_to_match(m, visible_height);
+            editor.set_status("Replace? (y)es / (n)o / (a)ll / Esc to stop");
+            term.draw(|f| draw_workspace(f, workspace, true))?;
+            match macros::read()? {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(c),
+                    ..
+                }) => c.to_ascii_lowercase(),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Esc, ..
+                }) => break,
+                _ => continue,
+            }
+        };
+
+        match answer {
+            'y' | 'a' => {
+                replace_all = answer == 'a';
+                let len = workspace.editor_mut().replace_match(m, replacement);
+                replaced += 1;
+                (y, x) = (m.y, m.x + len);
+            }
+            'n' => (y, x) = (m.y, m.x + 1),
+            _ => {}
+        }
+    }
+
+    let editor = workspace.editor_mut();
+    editor.current_match = None;
+    editor.set_status(format!("Replaced {} occurrence(s)", replaced));
+    Ok(())
+}
+
+/// Draw the tab bar and the visible buffers
+fn draw_workspace(
+    f: &mut Frame<CrosstermBackend<Output>>,
+    workspace: &Workspace,
+    show_cursor: bool,
+) {
+    let size = f.size();
+    if size.width < MIN_TERMINAL_WIDTH || size.height < MIN_TERMINAL_HEIGHT {
+        draw_too_small(f, &workspace.theme);
+        return;
+    }
+    let (tab_bar, panes) = workspace.layout(size);
+
+    if workspace.tab_bar_height() > 0 {
+        let theme = &workspace.theme;
+        let tabs = workspace
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(i, editor)| {
+                let marker = if editor.modified { "*" } else { "" };
+                let style = if i == workspace.active {
+                    Style::default()
+                        .fg(theme.highlight_fg)
+                        .bg(theme.border)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(theme.status_fg).bg(theme.status_bg)
+                };
+                Span::styled(format!(" {}: {}{} ", i + 1, editor.filename, marker), style)
+            })
+            .collect::<Vec<_>>();
+        let bar = Paragraph::new(Line::from(tabs)).style(Style::default().bg(theme.status_bg));
+        f.render_widget(bar, tab_bar);
+    }
+
+    for (index, area, focused) in panes {
+        draw_editor(
+            f,
+            area,
+            &workspace.buffers[index],
+            &workspace.theme,
+            &workspace.placeholder,
+            show_cursor && focused,
+        );
+    }
+}
+
+/// Notice shown in place of the editor while the terminal is below the minimum size
+fn draw_too_small(f: &mut Frame<CrosstermBackend<Output>>, theme: &Theme) {
+    let size = f.size();
+    let text = format!(
+        "Terminal too small ({}x{}, need {}x{})",
+        size.width, size.height, MIN_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT
+    );
+    let offset = size.height.saturating_sub(1) / 2;
+    let area = Rect {
+        y: size.y + offset,
+        height: size.height - offset,
+        ..size
+    };
+    f.render_widget(
+        Block::default().style(Style::default().fg(theme.foreground).bg(theme.background)),
+        size,
+    );
+    f.render_widget(
+        Paragraph::new(text)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true }),
+        area,
+    );
+}
+
+/// Draw the text area and status bar for `editor` inside `area`. `placeholder` is shown
+/// while the buffer is empty.
+fn draw_editor(
+    f: &mut Frame<CrosstermBackend<Output>>,
+    area: Rect,
+    editor: &Editor,
+    theme: &Theme,
+    placeholder: &str,
+    show_cursor: bool,
+) {
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+        .split(area);
+
+    let visible_lines = layout[0].height.saturating_sub(2) as usize;
+    let gutter_width = editor.gutter_width();
+    let brackets = match editor.bracket_match() {
+        Some((at, Some(partner))) => {
+            let style = Style::default()
+                .fg(theme.highlight_fg)
+                .bg(theme.bracket_match);
+            vec![(at, style), (partner, style)]
+        }
+        Some((at, None)) => vec![(
+            at,
+            Style::default()
+                .fg(theme.bracket_unmatched)
+                .add_modifier(Modifier::BOLD),
+        )],
+        None => Vec::new(),
+    };
+    let inner = layout[0].inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    let text_width = (inner.width as usize).saturating_sub(gutter_width);
+    let changes = if gutter_width > 0 {
+        editor.line_changes()
+    } else {
+        Rc::default()
+    };
+
+    // Screen rows with the buffer line each belongs to. A soft wrapped line is cut into
+    // several rows; only its first one is numbered.
+    let mut rows: Vec<(usize, Line<'static>)> = Vec::new();
+    for (y, l) in (editor.scroll_y..).zip(editor.content.iter_from(editor.scroll_y)) {
+        if rows.len() >= visible_lines {
+            break;
+        }
+        let rendered = render_line(l, y, editor, theme, &brackets);
+        let starts = editor.wrap_starts(y, text_width);
+        for (i, &start) in starts.iter().enumerate() {
+            let from = editor.display_col(y, start);
+            let width = starts
+                .get(i + 1)
+                .map_or(usize::MAX, |&next| editor.display_col(y, next) - from);
+            let mut line = clip_line(rendered.clone(), from + editor.scroll_x, width);
+            // A bar before the number marks lines changed since opening or saving
+            let bar = match changes.get(y) {
+                Some(LineChange::Added) => Span::styled("▎", Style::default().fg(theme.added)),
+                Some(LineChange::Modified) => {
+                    Span::styled("▎", Style::default().fg(theme.modified))
+                }
+                _ => Span::raw(" "),
+            };
+            if gutter_width > 0 && i > 0 {
+                line.spans
+                    .splice(0..0, [bar, Span::raw(" ".repeat(gutter_width - 1))]);
+            } else if gutter_width > 0 {
+                let number = format!("{:>width$}", y + 1, width = gutter_width - 2);
+                // The first mark on the line takes the place of the gap after the number
+                let mark = editor
+                    .marks
+                    .iter()
+                    .filter(|&(_, &(mark_y, _))| mark_y == y)
+                    .map(|(&name, _)| name)
+                    .min();
+                let gap = match mark {
+                    Some(name) => Span::styled(
+                        name.to_string(),
+                        Style::default().fg(theme.mark).add_modifier(Modifier::BOLD),
+                    ),
+                    None => Span::raw(" "),
+                };
+                line.spans.splice(
+                    0..0,
+                    [
+                        bar,
+                        Span::styled(number, Style::default().fg(theme.gutter)),
+                        gap,
+                    ],
+                );
+            }
+            rows.push((y, line));
+        }
+    }
+    rows.truncate(visible_lines);
+    let cursor_rows = rows.iter().filter(|(y, _)| *y == editor.cursor_y).count();
+    let first_cursor_row = rows.iter().position(|(y, _)| *y == editor.cursor_y);
+    let content_to_show = rows.into_iter().map(|(_, line)| line).collect::<Vec<_>>();
+
+    let main_block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.border))
+        .title(Span::styled(
+            "Rano — Text Editor",
+            Style::default()
+                .fg(theme.border)
+                .add_modifier(Modifier::BOLD),
+        ));
+
+    let paragraph = Paragraph::new(content_to_show)
+        .block(main_block)
+        .style(Style::default().fg(theme.foreground));
+    f.render_widget(
+        Block::default().style(Style::default().bg(theme.background)),
+        layout[0],
+    );
+
+    // Paint the cursor line's rows first; the text keeps this background where it sets none
+    if editor.highlight_current_line
+        && let Some(first) = first_cursor_row
+    {
+        let row = Rect {
+            y: inner.y + first as u16,
+            height: cursor_rows as u16,
+            ..inner
+        };
+        let highlight = Block::default().style(Style::default().bg(theme.current_line));
+        f.render_widget(highlight, row);
+    }
+
+    f.render_widget(paragraph, layout[0]);
+
+    // Centered below the first row so it stays clear of the cursor; typing anything hides it
+    if editor.is_empty() && inner.height > 2 {
+        let middle = Rect {
+            y: inner.y + inner.height / 2,
+            height: 1,
+            ..inner
+        };
+        let hint = Paragraph::new(Span::styled(placeholder, Style::default().fg(theme.gutter)))
+            .alignment(Alignment::Center);
+        f.render_widget(hint, middle);
+    }
+
+    // Scrollbar thumb over the right border, sized by the fraction of lines on screen
+    let total = editor.content.len();
+    if visible_lines > 0 && total > visible_lines {
+        let thumb = (visible_lines * visible_lines / total).max(1);
+        let max_scroll = total - visible_lines;
+        let top = editor.scroll_y.min(max_scroll) * (visible_lines - thumb) / max_scroll;
+        let bar = Rect {
+            x: layout[0].right().saturating_sub(1),
+            y: inner.y + top as u16,
+            width: 1,
+            height: thumb as u16,
+        };
+        let thumb_lines = vec![Line::from("█"); thumb];
+        f.render_widget(
+            Paragraph::new(thumb_lines).style(Style::default().fg(theme.border)),
+            bar,
+        );
+    }
+
+    // A lazily loaded file may have more lines than read so far
+    let more = if editor.is_partially_loaded() {
+        "+"
+    } else {
+        ""
+    };
+    let mut position = format!(
+        "Line: {}/{}{} | Col: {}",
+        editor.cursor_y + 1,
+        total,
+        more,
+        editor.cursor_x + 1
+    );
+    if editor.current_match.is_some() && editor.match_position.1 > 0 {
+        let (index, count) = editor.match_position;
+        position.push_str(&format!(" | [{}/{}]", index, count));
+    }
+    let saved = if editor.modified { "Modified" } else { "Saved" };
+    let mut details = format!(
+        "{} | {} | {} | {} | {} | {} | {}",
+        position,
+        view_position(
+            editor.scroll_y,
+            visible_lines,
+            total,
+            editor.is_partially_loaded()
+        ),
+        if editor.overwrite { "OVR" } else { "INS" },
+        editor.line_ending.name(),
+        editor.encoding.name(),
+        if editor.use_spaces { "Spaces" } else { "Tabs" },
+        saved
+    );
+    if editor.bom {
+        details.push_str(" | [BOM]");
+    }
+    if editor.read_only {
+        details.push_str(" | [READ-ONLY]");
+    }
+    if macros::is_recording() {
+        details.push_str(" | [REC]");
+    }
+    match editor.file_status {
+        FileStatus::New => details.push_str(" | [New File]"),
+        FileStatus::Empty if editor.is_empty() => details.push_str(" | [Empty]"),
+        _ => {}
+    }
+    // The filename gets whatever room is left; when there is too little, only the cursor
+    // position and modified state are kept
+    let room = (layout[1].width as usize)
+        .saturating_sub(details.chars().count() + "File: ".len() + " | ".len());
+    let mut status = if room >= MIN_FILENAME_WIDTH {
+        format!(
+            "File: {} | {}",
+            truncate_path(&editor.filename, room),
+            details
+        )
+    } else {
+        format!("{} | {}", position, saved)
+    };
+    if let Some(message) = editor.status_message() {
+        status.push_str(" | ");
+        status.push_str(message);
+    }
+    let status_bar = Paragraph::new(Line::from(Span::styled(
+        status,
+        Style::default()
+            .bg(theme.status_bg)
+            .fg(theme.status_fg)
+            .add_modifier(Modifier::BOLD),
+    )))
+    .style(Style::default().bg(theme.status_bg));
+    f.render_widget(status_bar, layout[1]);
+
+    // The cursor may be outside the view until the next key scrolls to it, e.g. after
+    // a jump in the other pane of a split; it is only drawn when on screen
+    if show_cursor && let Some((row, col)) = editor.cursor_on_screen(visible_lines, text_width) {
+        let x = (gutter_width + col) as u16 + 1;
+        f.set_cursor(layout[0].x + x, layout[0].y + row as u16 + 1);
+    }
+}
+
+/// Shorten `path` to at most `max` characters by replacing its leading components with
+/// `…`, cutting into the file name itself only when that alone is too long
+fn truncate_path(path: &str, max: usize) -> String {
+    let len = path.chars().count();
+    if len <= max {
+        return path.to_string();
+    }
+    let budget = max.saturating_sub(1);
+    let suffix = path
+        .char_indices()
+        .filter(|&(_, c)| c == '/')
+        .map(|(i, _)| &path[i..])
+        .find(|rest| rest.chars().count() <= budget)
+        .map(str::to_string)
+        .unwrap_or_else(|| path.chars().skip(len - budget).collect());
+    format!("…{}", suffix)
+}
+
+/// Where the view is in the file, like vim: `All` when every line fits, `Top` and `Bot` at
+/// either end, otherwise the percentage scrolled through the file
+fn view_position(scroll_y: usize, visible_lines: usize, total: usize, partial: bool) -> String {
+    let at_end = scroll_y + visible_lines >= total && !partial;
+    match (scroll_y == 0, at_end) {
+        (true, true) => "All".into(),
+        (true, false) => "Top".into(),
+        (false, true) => "Bot".into(),
+        (false, false) => {
+            let percent = scroll_y * 100 / total.saturating_sub(visible_lines).max(1);
+            format!("{}%", percent.min(99))
+        }
+    }
+}
+
+/// Build the styled display line for buffer line `y`, highlighting the current search match.
+/// `marks` gives extra styles for single characters, such as matching brackets.
+fn render_line(
+    line: &str,
+    y: usize,
+    editor: &Editor,
+    theme: &Theme,
+    marks: &[(Position, Style)],
+) -> Line<'static> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut styles = vec![Style::default(); chars.len()];
+
+    if let Some(lang) = editor.language() {
+        let state = editor.syntax_states.get(y).copied().unwrap_or_default();
+        let (tokens, _) = syntax::highlight_line(lang, &chars, state);
+        for (style, token) in styles.iter_mut().zip(tokens) {
+            *style = token_style(token, theme);
+        }
+    }
+
+    let trailing = trim_trailing_whitespace(line).chars().count();
+    if editor.show_whitespace {
+        for (i, (style, c)) in styles.iter_mut().zip(&chars).enumerate() {
+            if *c == ' ' || *c == '\t' {
+                *style = style.fg(if i >= trailing {
+                    theme.trailing_whitespace
+                } else {
+                    theme.whitespace
+                });
+            }
+        }
+    }
+    if editor.highlight_trailing {
+        for style in &mut styles[trailing..] {
+            *style = style.fg(theme.highlight_fg).bg(theme.trailing_whitespace);
+        }
+    }
+
+    for &((mark_y, x), style) in marks {
+        if mark_y == y && x < chars.len() {
+            styles[x] = style;
+        }
+    }
+
+    for m in editor.matches_in_line(y) {
+        let style = if editor.current_match == Some(m) {
+            Style::default()
+                .fg(theme.highlight_fg)
+                .bg(theme.active_match)
+        } else {
+            Style::default()
+                .fg(theme.highlight_fg)
+                .bg(theme.search_match)
+        };
+        styles[m.x..m.x + m.len].fill(style);
+    }
+
+    if let Some(((start_y, start_x), (end_y, end_x))) = editor.selection()
+        && (start_y..=end_y).contains(&y)
+    {
+        let from = if y == start_y { start_x } else { 0 };
+        let to = if y == end_y { end_x } else { chars.len() };
+        for style in &mut styles[from.min(chars.len())..to.min(chars.len())] {
+            *style = style.add_modifier(Modifier::REVERSED);
+        }
+    }
+
+    styled_spans(&chars, &styles, editor.tab_width, editor.show_whitespace)
+}
+
+/// Color for a syntax token
+fn token_style(token: Token, theme: &Theme) -> Style {
+    match token {
+        Token::Plain => Style::default(),
+        Token::Keyword => Style::default().fg(theme.keyword),
+        Token::String => Style::default().fg(theme.string),
+        Token::Comment => Style::default().fg(theme.comment),
+        Token::Number => Style::default().fg(theme.number),
+    }
+}
+
+/// Group characters with identical styles into spans, expanding tabs to the next tab stop.
+/// With `show_whitespace`, spaces are drawn as `·` and tabs start with `→`.
+fn styled_spans(
+    chars: &[char],
+    styles: &[Style],
+    tab_width: usize,
+    show_whitespace: bool,
+) -> Line<'static> {
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    let mut text = String::new();
+    let mut current = styles.first().copied().unwrap_or_default();
+    let mut col = 0;
+    for (&ch, &style) in chars.iter().zip(styles) {
+        if style != current {
+            spans.push(Span::styled(std::mem::take(&mut text), current));
+            current = style;
+        }
+        let width = char_width(ch, col, tab_width);
+        col += width;
+        match ch {
+            '\t' if show_whitespace && width > 0 => {
+                text.push('→');
+                text.extend(std::iter::repeat_n(' ', width - 1));
+            }
+            '\t' => text.extend(std::iter::repeat_n(' ', width)),
+            ' ' if show_whitespace => text.push('·'),
+            _ => text.push(ch),
+        }
+    }
+    spans.push(Span::styled(text, current));
+    Line::from(spans)
+}
+
+/// Keep `width` display columns of a rendered line starting at column `skip`, for
+/// horizontal scrolling and for cutting soft wrapped lines into rows
+fn clip_line(line: Line<'static>, skip: usize, width: usize) -> Line<'static> {
+    let mut remaining = skip;
+    let mut room = width;
+    let spans = line
+        .spans
+        .into_iter()
+        .filter_map(|span| {
+            let len = span.content.chars().count();
+            if remaining >= len || room == 0 {
+                remaining = remaining.saturating_sub(len);
+                return None;
+            }
+            let text: String = span.content.chars().skip(remaining).take(room).collect();
+            remaining = 0;
+            room -= text.chars().count();
+            Some(Span::styled(text, span.style))
+        })
+        .collect::<Vec<_>>();
+    Line::from(spans)
+}
+
+/// Bordered block with a title for prompts and popups
+fn popup_block<'a>(title: &'a str, theme: &Theme) -> Block<'a> {
+    Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.border))
+        .style(Style::default().fg(theme.foreground).bg(theme.background))
+}
+
 /// Creates a centered rectangle for popups
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
@@ -215,116 +1410,733 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         .split(popup_layout[1])[1]
 }
 
+/// Rectangle `height` rows tall and `percent_x` percent wide, centered just above the
+/// bottom row of `r` so it doesn't hide the middle of the view
+fn bottom_rect(percent_x: u16, height: u16, r: Rect) -> Rect {
+    let width = (u32::from(r.width) * u32::from(percent_x) / 100) as u16;
+    Rect {
+        x: r.x + r.width.saturating_sub(width) / 2,
+        y: (r.y + r.height).saturating_sub(height + 1).max(r.y),
+        width,
+        height: height.min(r.height),
+    }
+}
+
+/// `text` with its first letter in upper case
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
+/// Leave raw mode and the alternate screen and show the cursor again
+fn restore_terminal(output: &mut impl Write) -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(
+        output,
+        Show,
+        DisableBracketedPaste,
+        DisableMouseCapture,
+        LeaveAlternateScreen
+    )
+}
+
+/// Print command-line usage and exit with an error code
+fn usage() -> ! {
+    eprintln!(
+        "Usage: rano [--view] [--no-line-numbers] [--no-line-highlight] [--no-syntax] \
+         [--no-auto-pairs] [--strip-trailing] [--autosave SECS] [--stdout] \
+         [--theme dark|light] [--cwd DIR] [--oneshot] [FILE|-]..."
+    );
+    process::exit(2);
+}
+
 fn main() -> io::Result<()> {
+    let mut filenames = Vec::new();
+    let mut line_numbers = true;
+    let mut line_highlight = true;
+    let mut syntax_highlighting = true;
+    let mut auto_pairs = true;
+    let mut strip_trailing = None;
+    let mut autosave = None;
+    let mut to_stdout = false;
+    let mut read_only = false;
+    let mut theme_name = None;
+    let mut cwd = None;
+    let mut oneshot = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-line-numbers" => line_numbers = false,
+            "--no-line-highlight" => line_highlight = false,
+            "--no-syntax" => syntax_highlighting = false,
+            "--no-auto-pairs" => auto_pairs = false,
+            "--strip-trailing" => strip_trailing = Some(true),
+            "--autosave" => {
+                let secs = args.next().and_then(|s| s.parse().ok());
+                autosave = Some(secs.unwrap_or_else(|| usage()));
+            }
+            "--view" => read_only = true,
+            "--stdout" => to_stdout = true,
+            "--oneshot" => oneshot = true,
+            "--theme" => theme_name = Some(args.next().unwrap_or_else(|| usage())),
+            "--cwd" => cwd = Some(args.next().unwrap_or_else(|| usage())),
+            _ if arg.starts_with("--") => usage(),
+            _ => filenames.push(arg),
+        }
+    }
+
+    // Relative paths, including the files above and those opened later, resolve
+    // against this directory from here on
+    if let Some(dir) = cwd
+        && let Err(e) = env::set_current_dir(&dir)
+    {
+        eprintln!("rano: {}: {}", dir, e);
+        process::exit(1);
+    }
+
+    let config = Config::load();
+    let (keymap, keymap_errors) = Keymap::from_config(&config);
+    let (theme, theme_errors) = Theme::from_config(&config, theme_name.as_deref());
+    let mut config_errors: Vec<String> = config
+        .errors
+        .iter()
+        .cloned()
+        .chain(keymap_errors)
+        .chain(theme_errors)
+        .collect();
+    let strip_trailing = strip_trailing
+        .or(config.get_bool("editor", "strip_trailing", &mut config_errors))
+        .unwrap_or(false);
+    let mut comment_prefixes = Vec::new();
+    for entry in config.section("comments") {
+        match &entry.value {
+            Value::Str(prefix) if !prefix.is_empty() => {
+                comment_prefixes.push((entry.key.to_ascii_lowercase(), prefix.clone()))
+            }
+            _ => config_errors.push(format!(
+                "line {}: comment prefix for `{}` must be a non-empty string",
+                entry.line, entry.key
+            )),
+        }
+    }
+    let highlight_trailing = config
+        .get_bool("editor", "highlight_trailing", &mut config_errors)
+        .unwrap_or(true);
+    let remember_position = config
+        .get_bool("editor", "remember_position", &mut config_errors)
+        .unwrap_or(true);
+    // 0 turns blinking off
+    let blink_interval = config
+        .get_number("editor", "blink_interval", &mut config_errors)
+        .unwrap_or(DEFAULT_BLINK_INTERVAL);
+    let blink_interval = (blink_interval > 0).then(|| Duration::from_millis(blink_interval));
+    let date_format = config
+        .get_str("editor", "date_format", &mut config_errors)
+        .unwrap_or_else(|| DEFAULT_DATE_FORMAT.to_string());
+    let reflow_width = config
+        .get_number("editor", "reflow_width", &mut config_errors)
+        .filter(|&width| width > 0)
+        .map_or(DEFAULT_REFLOW_WIDTH, |width| width as usize);
+    let use_spaces = config
+        .get_bool("editor", "use_spaces", &mut config_errors)
+        .unwrap_or(true);
+    let soft_wrap = config
+        .get_bool("editor", "soft_wrap", &mut config_errors)
+        .unwrap_or(false);
+    let autosave = autosave
+        .or(config.get_number("editor", "autosave", &mut config_errors))
+        .filter(|&secs| secs > 0)
+        .map(Duration::from_secs);
+    for error in &config_errors {
+        eprintln!("rano: config: {}", error);
+    }
+
+    // Piped input has to be read before the terminal is taken over
+    if filenames.is_empty() && !io::stdin().is_terminal() {
+        filenames.push("-".to_string());
+    }
+    let mut stdin_text = None;
+    if filenames.iter().any(|f| f == "-") {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        stdin_text = Some(text);
+    }
+
+    if filenames.is_empty() {
+        filenames.push(DEFAULT_FILENAME.to_string());
+    }
+    // Settings from the command line and config file, applied to every buffer
+    let configure = |editor: &mut Editor| {
+        editor.show_line_numbers = line_numbers;
+        editor.highlight_current_line = line_highlight;
+        editor.strip_trailing = strip_trailing;
+        editor.highlight_trailing = highlight_trailing;
+        editor.remember_position = remember_position;
+        editor.soft_wrap = soft_wrap;
+        editor.use_spaces = use_spaces;
+        editor.syntax_highlighting = syntax_highlighting;
+        if !auto_pairs {
+            editor.auto_pairs.clear();
+        }
+        // Configured prefixes are checked before the defaults
+        editor
+            .comment_prefixes
+            .splice(0..0, comment_prefixes.iter().cloned());
+        editor.read_only = read_only;
+    };
+    let mut buffers = Vec::new();
+    for filename in filenames {
+        let mut editor = match (filename.as_str(), &stdin_text) {
+            ("-", Some(text)) => Editor::from_stdin(text),
+            // Fail before taking over the terminal so the message stays readable
+            _ => Editor::open(filename).unwrap_or_else(|e| {
+                eprintln!("rano: {}", e);
+                process::exit(1);
+            }),
+        };
+        configure(&mut editor);
+        let _ = recent::add(&editor.filename);
+        buffers.push(editor);
+    }
+
+    // Put the terminal back before a panic message is printed, or it would be garbled
+    // and the shell left in raw mode
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = if to_stdout {
+            restore_terminal(&mut io::stderr())
+        } else {
+            restore_terminal(&mut io::stdout())
+        };
+        default_hook(info);
+    }));
+
     enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, Hide)?;
-    let backend = CrosstermBackend::new(stdout);
+    // With --stdout the buffer is written to stdout on exit, so draw on stderr instead
+    let mut output: Output = if to_stdout {
+        Box::new(BufWriter::new(io::stderr()))
+    } else {
+        Box::new(io::stdout())
+    };
+    execute!(
+        output,
+        EnterAlternateScreen,
+        EnableBracketedPaste,
+        EnableMouseCapture,
+        Hide
+    )?;
+    let backend = CrosstermBackend::new(output);
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
-    let mut editor = Editor::open("untitled.txt".into())?;
+    let mut workspace = Workspace::new(buffers);
+    workspace.theme = theme;
+    // Keys are taken from the keymap so the hint follows the configured bindings
+    let key_name = |action| {
+        keymap
+            .keys_for(action)
+            .next()
+            .map_or_else(|| "?".to_string(), |key| key.to_string())
+    };
+    workspace.placeholder = format!(
+        "Rano — {} to save, {} to quit, {} for help",
+        key_name(Action::Save),
+        key_name(Action::Quit),
+        key_name(Action::Help)
+    );
+    if !config_errors.is_empty() {
+        workspace
+            .editor_mut()
+            .set_status(format!("Config: {}", config_errors.join("; ")));
+    }
+    for index in 0..workspace.buffers.len() {
+        if !workspace.buffers[index].has_swap() {
+            continue;
+        }
+        workspace.active = index;
+        let editor = workspace.editor_mut();
+        let answer = prompt_input(
+            &mut terminal,
+            &theme,
+            &format!("Recover unsaved changes to {}? (y/n)", editor.filename),
+        )?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            editor.remove_swap();
+        } else if let Err(e) = editor.recover_swap() {
+            editor.set_status(format!("Error reading swap file: {}", e));
+        }
+    }
+    workspace.active = 0;
+    let visible_height = workspace.visible_height(terminal.size()?);
+    for editor in workspace.buffers.iter_mut().filter(|e| e.remember_position) {
+        if let Some(position) = positions::load(&editor.filename) {
+            editor.restore_position(position, visible_height);
+        }
+    }
+    let mut clipboard = Clipboard::new();
     let mut last_blink = Instant::now();
+    let mut last_input = Instant::now();
     let mut show_cursor = true;
+    // Whether a buffer was closed with its changes discarded, for the --oneshot exit status
+    let mut quit_unsaved = false;
+    // The screen only needs drawing after input, a resize, a cursor blink or a status
+    // bar change
+    let mut dirty = true;
+    let mut status_shown = false;
 
     loop {
-        terminal.draw(|f| {
-            let size = f.size();
-            let layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
-                .split(size);
-
-            let visible_lines = (layout[0].height - 2) as usize;
-            let content_to_show = editor
-                .content
-                .iter()
-                .skip(editor.scroll_y)
-                .take(visible_lines)
-                .map(|l| Line::from(Span::raw(l.clone())))
-                .collect::<Vec<_>>();
-
-            let main_block = Block::default().borders(Borders::ALL).title(Span::styled(
-                "Rano — Text Editor",
-                Style::default()
-                    .fg(Color::Cyan)
-                    .add_modifier(Modifier::BOLD),
-            ));
-
-            let paragraph = Paragraph::new(content_to_show)
-                .block(main_block)
-                .style(Style::default().fg(Color::White));
-
-            f.render_widget(paragraph, layout[0]);
-
-            let status = format!(
-                "File: {} | Line: {} | Col: {} | {}",
-                editor.filename,
-                editor.cursor_y + 1,
-                editor.cursor_x + 1,
-                if editor.modified { "Modified" } else { "Saved" }
-            );
-            let status_bar = Paragraph::new(Line::from(Span::styled(
-                status,
-                Style::default()
-                    .bg(Color::DarkGray)
-                    .fg(Color::White)
-                    .add_modifier(Modifier::BOLD),
-            )));
-            f.render_widget(status_bar, layout[1]);
-
-            if show_cursor {
-                let x = editor.cursor_x as u16 + 1;
-                let y = (editor.cursor_y - editor.scroll_y) as u16 + 1;
-                f.set_cursor(layout[0].x + x, layout[0].y + y);
+        // Status messages disappear on their own after a while
+        let status_now = workspace
+            .buffers
+            .iter()
+            .any(|editor| editor.status_message().is_some());
+        if status_now != status_shown {
+            status_shown = status_now;
+            dirty = true;
+        }
+        if dirty {
+            let height = terminal.size()?.height as usize;
+            let visible = [Some(workspace.active), workspace.split];
+            for index in visible.into_iter().flatten() {
+                let editor = &mut workspace.buffers[index];
+                editor.ensure_loaded(editor.scroll_y + height + LOAD_AHEAD);
+                editor.update_syntax(editor.scroll_y + height);
             }
-        })?;
+            terminal.draw(|f| draw_workspace(f, &workspace, show_cursor))?;
+            dirty = false;
+        }
 
-        if last_blink.elapsed() >= Duration::from_millis(500) {
+        if let Some(interval) = blink_interval
+            && last_blink.elapsed() >= interval
+        {
             show_cursor = !show_cursor;
             last_blink = Instant::now();
+            dirty = true;
         }
 
-        if event::poll(Duration::from_millis(50))? {
-            if let Event::Key(KeyEvent {
+        if last_input.elapsed() >= SWAP_DELAY {
+            workspace.buffers.iter_mut().for_each(Editor::write_swap);
+        }
+        if let Some(interval) = autosave
+            && last_input.elapsed() >= interval
+        {
+            workspace.buffers.iter_mut().for_each(Editor::autosave);
+            last_input = Instant::now();
+            dirty = true;
+        }
+
+        if !macros::poll(Duration::from_millis(50))? {
+            continue;
+        }
+        last_input = Instant::now();
+        dirty = true;
+        let visible_height = workspace.visible_height(terminal.size()?);
+        let visible_width = workspace.visible_width(terminal.size()?);
+        let editor = workspace.editor_mut();
+        // Events before this key make up the macro if the key ends up stopping it
+        let macro_len = macros::recorded_len();
+        match macros::read()? {
+            Event::Key(KeyEvent {
                 code, modifiers, ..
-            }) = event::read()?
-            {
-                match (code, modifiers) {
-                    (KeyCode::Char('x'), KeyModifiers::CONTROL) => {
+            }) => {
+                // Shift+movement extends the selection; plain movement drops it
+                let modifiers = if motion_for(code).is_some() {
+                    if modifiers.contains(KeyModifiers::SHIFT) {
+                        editor.extend_selection();
+                    } else {
+                        editor.clear_selection();
+                    }
+                    modifiers - KeyModifiers::SHIFT
+                } else {
+                    modifiers
+                };
+                let action = match keymap.action(code, modifiers) {
+                    Some(Action::CommandLine) => {
+                        run_command_line(&mut terminal, &theme, editor, visible_height)?
+                    }
+                    action => action,
+                };
+                match action {
+                    // The buffer goes to stdout instead of being saved
+                    Some(Action::Quit) if to_stdout => {
+                        if confirm_quit_all(&mut terminal, &mut workspace, false)? {
+                            break;
+                        }
+                    }
+                    Some(Action::QuitAll) => {
+                        if confirm_quit_all(&mut terminal, &mut workspace, !to_stdout)? {
+                            quit_unsaved |= workspace.buffers.iter().any(|e| e.modified);
+                            break;
+                        }
+                    }
+                    Some(action @ (Action::Quit | Action::SaveQuit | Action::ForceQuit)) => {
                         if editor.modified {
-                            let save = prompt_input(
+                            // Whether the buffer stays open, e.g. because saving failed
+                            let keep = match action {
+                                Action::SaveQuit => !save_or_ask(&mut terminal, &theme, editor)?,
+                                // With --oneshot the file is known, so quitting saves it
+                                Action::Quit if oneshot => {
+                                    !save_or_ask(&mut terminal, &theme, editor)?
+                                }
+                                Action::ForceQuit => {
+                                    let answer = prompt_input(
+                                        &mut terminal,
+                                        &theme,
+                                        &format!(
+                                            "Discard unsaved changes in {}? (y/n)",
+                                            editor.filename
+                                        ),
+                                    )?;
+                                    !answer.trim().eq_ignore_ascii_case("y")
+                                }
+                                _ => {
+                                    let save = prompt_input(
+                                        &mut terminal,
+                                        &theme,
+                                        &format!(
+                                            "Unsaved changes in {}. Save? (y/n)",
+                                            editor.filename
+                                        ),
+                                    )?;
+                                    save.trim().eq_ignore_ascii_case("y")
+                                        && !save_or_ask(&mut terminal, &theme, editor)?
+                                }
+                            };
+                            if keep {
+                                continue;
+                            }
+                        }
+                        // Changes still there were discarded; an unmodified buffer is as
+                        // good as saved
+                        quit_unsaved |= editor.modified;
+                        if workspace.buffers.len() == 1 {
+                            break;
+                        }
+                        workspace.close_active();
+                    }
+                    Some(Action::Save) => {
+                        save_or_ask(&mut terminal, &theme, editor)?;
+                    }
+                    Some(Action::SaveAs) => save_as(&mut terminal, &theme, editor)?,
+                    Some(Action::Search) => {
+                        prompt_search(&mut terminal, &mut workspace, visible_height)?
+                    }
+                    Some(Action::Replace) if editor.can_edit() => {
+                        let query = prompt_input(&mut terminal, &theme, "Replace:")?;
+                        if !query.is_empty() {
+                            let replacement = prompt_input(&mut terminal, &theme, "Replace with:")?;
+                            replace_interactive(
                                 &mut terminal,
-                                "Unsaved changes. Save before exit? (y/n)",
+                                &mut workspace,
+                                query,
+                                &replacement,
+                                visible_height,
                             )?;
-                            if save.trim().eq_ignore_ascii_case("y") {
-                                editor.save(None)?;
+                        }
+                    }
+                    Some(Action::FindNext) => editor.find_next(visible_height),
+                    Some(Action::MatchBracket) => editor.jump_to_bracket(visible_height),
+                    Some(Action::SetMark) => {
+                        let message = "Set mark (0-9, a-z):";
+                        if let Some(name) = read_mark_name(&mut terminal, &mut workspace, message)?
+                        {
+                            workspace.editor_mut().set_mark(name);
+                        }
+                    }
+                    Some(Action::GotoMark) => {
+                        let message = "Go to mark (0-9, a-z):";
+                        if let Some(name) = read_mark_name(&mut terminal, &mut workspace, message)?
+                        {
+                            workspace.editor_mut().goto_mark(name, visible_height);
+                        }
+                    }
+                    Some(Action::GotoLine) => {
+                        let input =
+                            prompt_input(&mut terminal, &theme, "Go to line (or line:column):")?;
+                        match command::parse_position(&input) {
+                            Some((line, column)) => editor.goto_line(line, column, visible_height),
+                            None if input.trim().is_empty() => {}
+                            None => editor.set_status("Invalid line number"),
+                        }
+                    }
+                    Some(Action::RecordMacro) => {
+                        macros::start_recording();
+                        editor.set_status("Recording macro");
+                    }
+                    Some(Action::StopMacro) => match macros::stop_recording(macro_len) {
+                        Some(keys) => editor.set_status(format!("Recorded {} keys", keys)),
+                        None => editor.set_status("Not recording a macro"),
+                    },
+                    Some(Action::PlayMacro) if macros::is_recording() => {
+                        editor.set_status("Stop recording before playing the macro")
+                    }
+                    Some(Action::PlayMacro) => {
+                        let input = prompt_input(
+                            &mut terminal,
+                            &theme,
+                            "Play macro how many times? (Enter for once)",
+                        )?;
+                        let times = match input.trim() {
+                            "" => Ok(1),
+                            count => count.parse::<usize>(),
+                        };
+                        match times {
+                            Ok(times) if times > 0 => {
+                                if !macros::play(times) {
+                                    editor.set_status("No macro recorded");
+                                }
                             }
+                            _ => editor.set_status("Invalid count"),
                         }
-                        break;
                     }
-                    (KeyCode::Char('o'), KeyModifiers::CONTROL) => {
-                        let new_name = prompt_input(&mut terminal, "Save as:")?;
-                        if !new_name.is_empty() {
-                            editor.save(Some(new_name))?;
+                    Some(Action::RecentFiles) => {
+                        let files = recent::load();
+                        if files.is_empty() {
+                            editor.set_status("No recent files");
+                        } else if let Some(path) =
+                            pick_from_list(&mut terminal, &workspace, "Recent files", &files)?
+                        {
+                            open_buffer(
+                                &mut terminal,
+                                &mut workspace,
+                                &path,
+                                &configure,
+                                visible_height,
+                            )?;
                         }
                     }
-                    (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
-                        let query = prompt_input(&mut terminal, "Search for:")?;
-                        if !query.is_empty() {
-                            editor.search(query);
+                    Some(Action::FindFile) => {
+                        let files = list_files(Path::new("."));
+                        let title = "Open file (type to filter)";
+                        if let Some(path) =
+                            pick_from_list(&mut terminal, &workspace, title, &files)?
+                        {
+                            open_buffer(
+                                &mut terminal,
+                                &mut workspace,
+                                &path,
+                                &configure,
+                                visible_height,
+                            )?;
+                        }
+                    }
+                    Some(Action::InsertDate) => {
+                        editor.insert_text(&DateTime::now().format(&date_format))
+                    }
+                    Some(Action::LineEnding) => {
+                        let prompt = format!(
+                            "Line endings (lf/crlf), currently {}:",
+                            editor.line_ending.name()
+                        );
+                        let input = prompt_input(&mut terminal, &theme, &prompt)?;
+                        match LineEnding::parse(&input) {
+                            Some(ending) => editor.set_line_ending(ending),
+                            None if input.trim().is_empty() => {}
+                            None => editor.set_status("Line endings must be lf or crlf"),
                         }
                     }
-                    (KeyCode::Enter, _) => editor.insert_newline(),
-                    (KeyCode::Backspace, _) => editor.delete_char(),
-                    (KeyCode::Char(c), _) => editor.insert_char(c),
-                    (kc, _) => editor.move_cursor(kc, (terminal.size()?.height - 2) as usize),
+                    Some(Action::Help) => show_help(&mut terminal, &workspace, &keymap)?,
+                    Some(Action::Stats) => {
+                        let stats = editor.stats();
+                        let lines = vec![
+                            format!("Lines: {}", stats.lines),
+                            format!("Words: {}", stats.words),
+                            format!("Characters: {}", stats.chars),
+                            format!("Characters (no whitespace): {}", stats.chars_no_whitespace),
+                            format!(
+                                "Cursor: line {}, column {}",
+                                editor.cursor_y + 1,
+                                editor.cursor_x + 1
+                            ),
+                            String::new(),
+                            "Press any key to close".into(),
+                        ];
+                        show_popup(&mut terminal, &workspace, "Statistics", lines)?;
+                    }
+                    Some(Action::Reload) => {
+                        if editor.modified {
+                            let answer = prompt_input(
+                                &mut terminal,
+                                &theme,
+                                "Discard unsaved changes and reload? (y/n)",
+                            )?;
+                            if !answer.trim().eq_ignore_ascii_case("y") {
+                                continue;
+                            }
+                        }
+                        if let Err(e) = editor.reload() {
+                            editor.set_status(format!("Error reloading: {}", e));
+                        }
+                    }
+                    Some(Action::DeleteLine) => editor.delete_line(),
+                    Some(Action::DuplicateLine) => editor.duplicate_line(),
+                    Some(Action::Copy) => {
+                        clipboard.set(editor.copy_text());
+                        editor.set_status("Copied");
+                    }
+                    Some(Action::Cut) if editor.can_edit() => {
+                        clipboard.set(editor.cut_text());
+                        editor.set_status("Cut");
+                    }
+                    Some(Action::Paste) => editor.insert_text(&clipboard.get()),
+                    Some(Action::Undo) => editor.undo(),
+                    Some(Action::Redo) => editor.redo(),
+                    Some(Action::PrevBuffer) => workspace.prev(),
+                    Some(Action::NextBuffer) => workspace.next(),
+                    Some(Action::MoveLineUp) => editor.move_line_up(),
+                    Some(Action::MoveLineDown) => editor.move_line_down(),
+                    Some(Action::JoinLine) => editor.join_line(),
+                    Some(Action::Reflow) => editor.reflow(reflow_width),
+                    Some(Action::WordLeft) => editor.word_left(),
+                    Some(Action::WordRight) => editor.word_right(),
+                    Some(Action::ParagraphUp) => editor.paragraph_up(),
+                    Some(Action::ParagraphDown) => editor.paragraph_down(),
+                    Some(Action::FileStart) => editor.goto_start(),
+                    Some(Action::FileEnd) => editor.goto_end(visible_height),
+                    Some(Action::ToggleOverwrite) => editor.overwrite = !editor.overwrite,
+                    Some(Action::ToggleWhitespace) => {
+                        editor.show_whitespace = !editor.show_whitespace
+                    }
+                    Some(Action::ToggleWrap) => {
+                        editor.soft_wrap = !editor.soft_wrap;
+                        // Goal columns count from the line start or the row start
+                        editor.goal_col = None;
+                        editor.set_status(if editor.soft_wrap {
+                            "Soft wrap on"
+                        } else {
+                            "Soft wrap off"
+                        });
+                    }
+                    Some(Action::ToggleSplit) => workspace.toggle_split(),
+                    Some(Action::SwitchPane) => workspace.switch_pane(),
+                    Some(Action::ToggleView) => {
+                        editor.read_only = !editor.read_only;
+                        editor.set_status(if editor.read_only {
+                            "View mode on"
+                        } else {
+                            "View mode off"
+                        });
+                    }
+                    Some(Action::ClearSearch) => editor.clear_search(),
+                    Some(Action::Indent) => editor.insert_tab(),
+                    Some(Action::Dedent) => editor.dedent_line(),
+                    Some(Action::DeleteWordBack) => editor.delete_word_back(),
+                    Some(Action::Increment) => editor.increment_number(1),
+                    Some(Action::Decrement) => editor.increment_number(-1),
+                    Some(Action::ToggleComment) => editor.toggle_comment(),
+                    // Commands that aren't actions already ran on the command line
+                    Some(Action::CommandLine) => {}
+                    // Guarded arms fall through here when the guard fails
+                    Some(_) => {}
+                    None => match (code, modifiers) {
+                        (KeyCode::Char(c @ '1'..='9'), KeyModifiers::ALT) => {
+                            workspace.select(c as usize - '1' as usize)
+                        }
+                        (KeyCode::Enter, _) => editor.insert_newline(),
+                        // Terminals whose Backspace sends ^H report it as Ctrl+H
+                        (KeyCode::Backspace, _) | (KeyCode::Char('h'), KeyModifiers::CONTROL) => {
+                            editor.delete_char()
+                        }
+                        (KeyCode::Delete, _) => editor.delete_forward(),
+                        (KeyCode::Char(c), _) => editor.insert_char(c),
+                        (kc, _) => {
+                            if let Some(motion) = motion_for(kc) {
+                                editor.move_cursor(motion, visible_height, visible_width)
+                            }
+                        }
+                    },
                 }
             }
+            Event::Mouse(mouse) => handle_mouse(&mut workspace, mouse, terminal.size()?),
+            // Some terminals send bare carriage returns as line breaks in pasted text
+            Event::Paste(text) => {
+                editor.insert_text(&text.replace("\r\n", "\n").replace('\r', "\n"))
+            }
+            // Redraw at the new size straight away rather than on the next key
+            Event::Resize(width, height) => {
+                terminal.autoresize()?;
+                workspace.keep_cursors_visible(Rect::new(0, 0, width, height));
+                continue;
+            }
+            _ => {}
         }
+        // The active buffer or its gutter width may have changed
+        let visible_width = workspace.visible_width(terminal.size()?);
+        let editor = workspace.editor_mut();
+        editor.adjust_marks();
+        editor.scroll_y_to_cursor(visible_height, visible_width);
+        editor.scroll_x_to_cursor(visible_width);
     }
 
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), Show, LeaveAlternateScreen)?;
+    for editor in &mut workspace.buffers {
+        editor.store_position();
+        editor.remove_swap();
+    }
+    restore_terminal(terminal.backend_mut())?;
+    if to_stdout {
+        let mut stdout = io::stdout();
+        stdout.write_all(workspace.editor().text().as_bytes())?;
+        stdout.flush()?;
+    }
+    // Lets the program that started the editor tell a finished edit from an abandoned one
+    if oneshot && quit_unsaved {
+        process::exit(1);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{TerminalOptions, Viewport};
+
+    /// Terminal drawing into memory, with a fixed size so no tty is needed
+    fn test_terminal(width: u16, height: u16) -> Terminal<CrosstermBackend<Output>> {
+        let backend = CrosstermBackend::new(Box::new(io::sink()) as Output);
+        let viewport = Viewport::Fixed(Rect::new(0, 0, width, height));
+        Terminal::with_options(backend, TerminalOptions { viewport }).unwrap()
+    }
+
+    #[test]
+    fn resize_keeps_cursor_visible() {
+        let lines = (1..=50).map(|i| format!("line {}", i)).collect();
+        let mut editor = Editor::from_lines(lines);
+        editor.cursor_y = 40;
+        let mut workspace = Workspace::new(vec![editor]);
+        let area = Rect::new(0, 0, 40, 10);
+        workspace.keep_cursors_visible(area);
+        let editor = workspace.editor();
+        let height = workspace.visible_height(area);
+        assert!(editor.scroll_y <= 40 && 40 < editor.scroll_y + height);
+    }
+
+    #[test]
+    fn tiny_terminal_renders() {
+        let mut workspace = Workspace::new(vec![
+            Editor::from_lines(vec!["one".into()]),
+            Editor::from_lines(vec!["two".into()]),
+        ]);
+        workspace.toggle_split();
+        for (width, height) in [(1, 1), (5, 2), (MIN_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT)] {
+            let mut terminal = test_terminal(width, height);
+            terminal
+                .draw(|f| draw_workspace(f, &workspace, true))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn cursor_above_scroll_renders() {
+        let lines = (1..=50).map(|i| format!("line {}", i)).collect();
+        let mut editor = Editor::from_lines(lines);
+        editor.scroll_y = 30;
+        editor.cursor_y = 2;
+        let workspace = Workspace::new(vec![editor]);
+        let mut terminal = test_terminal(40, 10);
+        terminal
+            .draw(|f| draw_workspace(f, &workspace, true))
+            .unwrap();
+    }
+}
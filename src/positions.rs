@@ -0,0 +1,71 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Most files whose cursor position is remembered; the oldest entries are dropped first
+const MAX_ENTRIES: usize = 1000;
+
+/// `$XDG_STATE_HOME/rano`, falling back to `~/.local/state/rano`
+pub fn state_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    Some(base.join("rano"))
+}
+
+/// `positions` in the state directory
+pub fn default_path() -> Option<PathBuf> {
+    Some(state_dir()?.join("positions"))
+}
+
+/// Absolute path used as the key for `filename`
+fn key(filename: &str) -> Option<String> {
+    let path = fs::canonicalize(filename).ok()?;
+    path.to_str().map(str::to_string)
+}
+
+/// Entries of the positions file as `(line, column, path)`, oldest first.
+/// Each line of the file is `line<TAB>column<TAB>path`; malformed lines are skipped.
+fn read(path: &Path) -> Vec<(usize, usize, String)> {
+    let text = fs::read_to_string(path).unwrap_or_default();
+    text.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, '\t');
+            let y = parts.next()?.parse().ok()?;
+            let x = parts.next()?.parse().ok()?;
+            Some((y, x, parts.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// Last remembered `(line, column)` of the cursor in `filename`
+pub fn load(filename: &str) -> Option<(usize, usize)> {
+    let key = key(filename)?;
+    read(&default_path()?)
+        .into_iter()
+        .rev()
+        .find(|(_, _, path)| *path == key)
+        .map(|(y, x, _)| (y, x))
+}
+
+/// Remember the cursor position `(line, column)` for `filename`.
+/// Files that don't exist on disk are not recorded.
+pub fn store(filename: &str, (y, x): (usize, usize)) -> io::Result<()> {
+    let (Some(key), Some(path)) = (key(filename), default_path()) else {
+        return Ok(());
+    };
+    let mut entries = read(&path);
+    entries.retain(|(_, _, p)| *p != key);
+    entries.push((y, x, key));
+    let skip = entries.len().saturating_sub(MAX_ENTRIES);
+    let text: String = entries[skip..]
+        .iter()
+        .map(|(y, x, p)| format!("{}\t{}\t{}\n", y, x, p))
+        .collect();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
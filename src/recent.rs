@@ -0,0 +1,38 @@
+use std::{fs, io, path::Path};
+
+use crate::positions;
+
+/// Most files kept in the recent files list
+const MAX_ENTRIES: usize = 20;
+
+/// Recently opened files as absolute paths, most recent first. The list is kept in
+/// `recent` in the state directory, one path per line; files that no longer exist are
+/// left out.
+pub fn load() -> Vec<String> {
+    let Some(dir) = positions::state_dir() else {
+        return Vec::new();
+    };
+    let text = fs::read_to_string(dir.join("recent")).unwrap_or_default();
+    text.lines()
+        .filter(|line| Path::new(line).is_file())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Move `filename` to the top of the recent files list.
+/// Files that don't exist on disk are not recorded.
+pub fn add(filename: &str) -> io::Result<()> {
+    let (Ok(path), Some(dir)) = (fs::canonicalize(filename), positions::state_dir()) else {
+        return Ok(());
+    };
+    let Some(path) = path.to_str() else {
+        return Ok(());
+    };
+    let mut entries = load();
+    entries.retain(|entry| entry != path);
+    entries.insert(0, path.to_string());
+    entries.truncate(MAX_ENTRIES);
+    fs::create_dir_all(&dir)?;
+    let text: String = entries.iter().map(|entry| format!("{}\n", entry)).collect();
+    fs::write(dir.join("recent"), text)
+}
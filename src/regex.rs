@@ -0,0 +1,616 @@
+/// A small regular expression engine for search.
+///
+/// Patterns are compiled to a program run as a Pike VM: every way the pattern can match
+/// is followed in step through the text, so matching takes time linear in the text and
+/// needs no recursion, whatever the pattern. The results are those a backtracking
+/// engine would give. Supports literals, `.`, character classes (`[a-z]`, `[^"]`), the
+/// escapes `\d \w \s` and their negations, `\b`/`\B`, anchors `^` and `$`, groups with
+/// alternation (`(a|b)`, `(?:a|b)`) and the quantifiers `* + ? {n} {n,} {n,m}`,
+/// optionally lazy with a trailing `?`. Positions and lengths are counted in characters.
+#[derive(Clone, Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+    case_insensitive: bool,
+}
+
+/// Most instructions a compiled pattern may have, which bounded repeats multiply
+const MAX_PROGRAM: usize = 20_000;
+
+#[derive(Clone, Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Class),
+    LineStart,
+    LineEnd,
+    WordBoundary(bool),
+    Group(Vec<Vec<Node>>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Self::Digit => c.is_ascii_digit(),
+            Self::Word => is_word_char(c),
+            Self::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool),
+}
+
+#[derive(Clone, Debug)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+impl Class {
+    fn matches(&self, c: char, case_insensitive: bool) -> bool {
+        let hit = |c: char| {
+            self.items.iter().any(|item| match *item {
+                ClassItem::Range(lo, hi) => (lo..=hi).contains(&c),
+                ClassItem::Perl(perl, negated) => perl.matches(c) != negated,
+            })
+        };
+        let found = if case_insensitive {
+            hit(c) || c.to_lowercase().any(hit) || c.to_uppercase().any(hit)
+        } else {
+            hit(c)
+        };
+        found != self.negated
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Regex {
+    /// Compile `pattern`, describing the problem if it is invalid
+    pub fn new(pattern: &str, case_insensitive: bool) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let alternatives = parser.parse_alternatives()?;
+        if parser.pos < parser.chars.len() {
+            return Err("unmatched `)`".into());
+        }
+        let mut compiler = Compiler {
+            program: Vec::new(),
+        };
+        compiler.alternatives(&alternatives)?;
+        compiler.emit(Inst::Match)?;
+        Ok(Self {
+            program: compiler.program,
+            case_insensitive,
+        })
+    }
+
+    /// First match starting at or after character `from`, as `(start, length)`.
+    /// The leftmost match wins, and among those starting there the one a backtracking
+    /// engine would find first.
+    pub fn find_at(&self, chars: &[char], from: usize) -> Option<(usize, usize)> {
+        let vm = Vm {
+            program: &self.program,
+            chars,
+            case_insensitive: self.case_insensitive,
+        };
+        // Threads as `(instruction, match start)`, highest priority first
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        // Position each instruction was last added to a thread list at, plus one
+        let mut added = vec![0; self.program.len()];
+        let mut found = None;
+        for pos in from..=chars.len() {
+            // A match starting here ranks below every thread already running
+            if found.is_none() {
+                vm.add_thread(&mut current, &mut added, 0, pos, pos);
+            }
+            if current.is_empty() && found.is_some() {
+                break;
+            }
+            for &(pc, start) in &current {
+                let advance = match &self.program[pc] {
+                    Inst::Match => {
+                        found = Some((start, pos - start));
+                        // Threads after this one only lead to lower priority matches
+                        break;
+                    }
+                    Inst::Char(c) => chars.get(pos).is_some_and(|&ch| vm.same_char(ch, *c)),
+                    Inst::Any => pos < chars.len(),
+                    Inst::Class(class) => chars
+                        .get(pos)
+                        .is_some_and(|&ch| class.matches(ch, self.case_insensitive)),
+                    _ => unreachable!("only consuming instructions are queued"),
+                };
+                if advance {
+                    vm.add_thread(&mut next, &mut added, pc + 1, start, pos + 1);
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        found
+    }
+}
+
+/// One step of a compiled pattern
+#[derive(Clone, Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    LineStart,
+    LineEnd,
+    WordBoundary(bool),
+    /// Continue at both targets, preferring the first
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> Result<usize, String> {
+        if self.program.len() >= MAX_PROGRAM {
+            return Err("pattern too large".into());
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    /// Point the jump or split at `at` (its second target for a split) to `target`
+    fn patch(&mut self, at: usize, target: usize) {
+        match &mut self.program[at] {
+            Inst::Jump(to) | Inst::Split(_, to) => *to = target,
+            _ => unreachable!("only jumps and splits are patched"),
+        }
+    }
+
+    fn alternatives(&mut self, alternatives: &[Vec<Node>]) -> Result<(), String> {
+        let mut jumps = Vec::new();
+        for (i, sequence) in alternatives.iter().enumerate() {
+            let split = if i + 1 < alternatives.len() {
+                let at = self.program.len();
+                Some(self.emit(Inst::Split(at + 1, 0))?)
+            } else {
+                None
+            };
+            for node in sequence {
+                self.node(node)?;
+            }
+            if let Some(split) = split {
+                jumps.push(self.emit(Inst::Jump(0))?);
+                let next = self.program.len();
+                self.patch(split, next);
+            }
+        }
+        let end = self.program.len();
+        for jump in jumps {
+            self.patch(jump, end);
+        }
+        Ok(())
+    }
+
+    fn node(&mut self, node: &Node) -> Result<(), String> {
+        match node {
+            Node::Char(c) => self.emit(Inst::Char(*c)).map(drop),
+            Node::Any => self.emit(Inst::Any).map(drop),
+            Node::Class(class) => self.emit(Inst::Class(class.clone())).map(drop),
+            Node::LineStart => self.emit(Inst::LineStart).map(drop),
+            Node::LineEnd => self.emit(Inst::LineEnd).map(drop),
+            Node::WordBoundary(expected) => self.emit(Inst::WordBoundary(*expected)).map(drop),
+            Node::Group(alternatives) => self.alternatives(alternatives),
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.node(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.emit(Inst::Split(0, 0))?;
+                        self.node(node)?;
+                        self.emit(Inst::Jump(split))?;
+                        let end = self.program.len();
+                        self.program[split] = self.order(split + 1, end, *greedy);
+                    }
+                    Some(max) => {
+                        // Each optional copy can skip straight past the rest
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Split(0, 0))?);
+                            self.node(node)?;
+                        }
+                        let end = self.program.len();
+                        for split in splits {
+                            self.program[split] = self.order(split + 1, end, *greedy);
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// A split between another iteration at `body` and stopping at `end`
+    fn order(&self, body: usize, end: usize, greedy: bool) -> Inst {
+        if greedy {
+            Inst::Split(body, end)
+        } else {
+            Inst::Split(end, body)
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    /// Step over the current character, returning `value`
+    fn skip<T>(&mut self, value: T) -> T {
+        self.pos += 1;
+        value
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alternatives(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alternatives = vec![self.parse_sequence()?];
+        while self.eat('|') {
+            alternatives.push(self.parse_sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn parse_sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+        Ok(nodes)
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        let c = self.next().ok_or("unexpected end of pattern")?;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::LineStart,
+            '$' => Node::LineEnd,
+            '(' => {
+                // Non-capturing groups behave the same as plain ones here
+                if self.eat('?') && !self.eat(':') {
+                    return Err("unsupported group syntax".into());
+                }
+                let alternatives = self.parse_alternatives()?;
+                if !self.eat(')') {
+                    return Err("missing `)`".into());
+                }
+                Node::Group(alternatives)
+            }
+            '[' => Node::Class(self.parse_class()?),
+            '\\' => self.parse_escape()?,
+            '*' | '+' | '?' => return Err(format!("nothing to repeat before `{}`", c)),
+            c => Node::Char(c),
+        })
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, String> {
+        let c = self.next().ok_or("trailing `\\`")?;
+        let perl = |perl, negated| {
+            Node::Class(Class {
+                negated: false,
+                items: vec![ClassItem::Perl(perl, negated)],
+            })
+        };
+        Ok(match c {
+            'd' => perl(Perl::Digit, false),
+            'D' => perl(Perl::Digit, true),
+            'w' => perl(Perl::Word, false),
+            'W' => perl(Perl::Word, true),
+            's' => perl(Perl::Space, false),
+            'S' => perl(Perl::Space, true),
+            'b' => Node::WordBoundary(true),
+            'B' => Node::WordBoundary(false),
+            _ => Node::Char(escaped_char(c)?),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Class, String> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.next().ok_or("missing `]`")?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let lo = if c == '\\' {
+                let e = self.next().ok_or("trailing `\\`")?;
+                let perl = match e {
+                    'd' | 'D' => Some(Perl::Digit),
+                    'w' | 'W' => Some(Perl::Word),
+                    's' | 'S' => Some(Perl::Space),
+                    _ => None,
+                };
+                if let Some(perl) = perl {
+                    items.push(ClassItem::Perl(perl, e.is_ascii_uppercase()));
+                    continue;
+                }
+                escaped_char(e)?
+            } else {
+                c
+            };
+            // A `-` before the closing `]` is a literal
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let hi = match self.next() {
+                    Some('\\') => escaped_char(self.next().ok_or("trailing `\\`")?)?,
+                    Some(hi) => hi,
+                    None => return Err("missing `]`".into()),
+                };
+                if hi < lo {
+                    return Err(format!("invalid range `{}-{}`", lo, hi));
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+        Ok(Class { negated, items })
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => self.skip((0, None)),
+            Some('+') => self.skip((1, None)),
+            Some('?') => self.skip((0, Some(1))),
+            Some('{') => match self.parse_braces() {
+                Some(bounds) => bounds,
+                // Not a valid repetition, so `{` is matched literally
+                None => return Ok(atom),
+            },
+            _ => return Ok(atom),
+        };
+        if matches!(
+            atom,
+            Node::LineStart | Node::LineEnd | Node::WordBoundary(_)
+        ) {
+            return Err("nothing to repeat".into());
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err("invalid repetition bounds".into());
+        }
+        let greedy = !self.eat('?');
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    /// Parse `{n}`, `{n,}` or `{n,m}`, consuming it only if it is well formed
+    fn parse_braces(&mut self) -> Option<(usize, Option<usize>)> {
+        let rest: String = self.chars[self.pos + 1..].iter().collect();
+        let close = rest.find('}')?;
+        let inner = &rest[..close];
+        let (min, max) = match inner.split_once(',') {
+            Some((min, "")) => (min.parse().ok()?, None),
+            Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+            None => {
+                let n = inner.parse().ok()?;
+                (n, Some(n))
+            }
+        };
+        self.pos += inner.chars().count() + 2;
+        Some((min, max))
+    }
+}
+
+fn escaped_char(c: char) -> Result<char, String> {
+    Ok(match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        c if c.is_ascii_alphanumeric() => return Err(format!("unknown escape `\\{}`", c)),
+        c => c,
+    })
+}
+
+struct Vm<'a> {
+    program: &'a [Inst],
+    chars: &'a [char],
+    case_insensitive: bool,
+}
+
+impl Vm<'_> {
+    /// Queue the thread at `pc` for position `pos`, following jumps, splits and
+    /// assertions so only consuming instructions and `Match` end up in `list`, in
+    /// priority order. Instructions already queued for `pos` are skipped: the earlier
+    /// thread there has higher priority, and skipping also ends loops that consume nothing.
+    fn add_thread(
+        &self,
+        list: &mut Vec<(usize, usize)>,
+        added: &mut [usize],
+        pc: usize,
+        start: usize,
+        pos: usize,
+    ) {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if added[pc] == pos + 1 {
+                continue;
+            }
+            added[pc] = pos + 1;
+            let current = self.chars.get(pos).copied();
+            match self.program[pc] {
+                Inst::Jump(to) => stack.push(to),
+                Inst::Split(first, second) => {
+                    stack.push(second);
+                    stack.push(first);
+                }
+                Inst::LineStart => {
+                    if pos == 0 {
+                        stack.push(pc + 1);
+                    }
+                }
+                Inst::LineEnd => {
+                    if pos == self.chars.len() {
+                        stack.push(pc + 1);
+                    }
+                }
+                Inst::WordBoundary(expected) => {
+                    let before = pos > 0 && is_word_char(self.chars[pos - 1]);
+                    let after = current.is_some_and(is_word_char);
+                    if (before != after) == expected {
+                        stack.push(pc + 1);
+                    }
+                }
+                _ => list.push((pc, start)),
+            }
+        }
+    }
+
+    fn same_char(&self, a: char, b: char) -> bool {
+        a == b || (self.case_insensitive && a.to_lowercase().eq(b.to_lowercase()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// First match of `pattern` in `text` as the matched text
+    fn find(pattern: &str, text: &str) -> Option<String> {
+        let chars: Vec<char> = text.chars().collect();
+        let regex = Regex::new(pattern, false).unwrap();
+        regex
+            .find_at(&chars, 0)
+            .map(|(start, len)| chars[start..start + len].iter().collect())
+    }
+
+    #[test]
+    fn literals_classes_and_escapes() {
+        assert_eq!(find("lo", "hello").as_deref(), Some("lo"));
+        assert_eq!(find("h.l", "hello").as_deref(), Some("hel"));
+        assert_eq!(find("[a-c]+", "xxbcaz").as_deref(), Some("bca"));
+        assert_eq!(find("[^\"]+", "\"quoted\"").as_deref(), Some("quoted"));
+        assert_eq!(find(r"\d+", "abc 123").as_deref(), Some("123"));
+        assert_eq!(find(r"\w+\s\W", "ab c!").as_deref(), None);
+        assert_eq!(find(r"\bcat\b", "concat cat").as_deref(), Some("cat"));
+        assert_eq!(find(r"\Bcat", "concat cat").as_deref(), Some("cat"));
+        assert_eq!(find("a-b", "a-b").as_deref(), Some("a-b"));
+        assert_eq!(find("x", "abc"), None);
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(find("^ab", "abab").as_deref(), Some("ab"));
+        assert_eq!(find("b$", "abab").as_deref(), Some("b"));
+        assert_eq!(find("^b", "abab"), None);
+        assert_eq!(find("^$", "").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn priority_follows_backtracking() {
+        assert_eq!(find("a|ab", "ab").as_deref(), Some("a"));
+        assert_eq!(find("ab|a", "ab").as_deref(), Some("ab"));
+        assert_eq!(find("a.*b", "axbxb").as_deref(), Some("axbxb"));
+        assert_eq!(find("a.*?b", "axbxb").as_deref(), Some("axb"));
+        assert_eq!(find("a??", "aa").as_deref(), Some(""));
+        assert_eq!(find("(?:ab)+", "xababa").as_deref(), Some("abab"));
+    }
+
+    #[test]
+    fn counted_repeats() {
+        assert_eq!(find("a{2}", "aaaa").as_deref(), Some("aa"));
+        assert_eq!(find("a{2,}", "aaaa").as_deref(), Some("aaaa"));
+        assert_eq!(find("a{1,3}", "aaaa").as_deref(), Some("aaa"));
+        assert_eq!(find("a{1,3}?", "aaaa").as_deref(), Some("a"));
+        assert_eq!(find("a{x}", "a{x}").as_deref(), Some("a{x}"));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let chars: Vec<char> = "Hello WORLD".chars().collect();
+        let regex = Regex::new("world|[h]ello", true).unwrap();
+        assert_eq!(regex.find_at(&chars, 0), Some((0, 5)));
+        assert_eq!(regex.find_at(&chars, 1), Some((6, 5)));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for pattern in [
+            "(a", "a)", "*a", "[a", "[z-a]", r"\q", "^*", "a{3,1}", "(?<a)",
+        ] {
+            assert!(Regex::new(pattern, false).is_err(), "{}", pattern);
+        }
+        assert!(Regex::new("(a{100}){1000}", false).is_err());
+    }
+
+    #[test]
+    fn empty_loops_terminate() {
+        assert_eq!(find("(a*)*b", "aab").as_deref(), Some("aab"));
+        assert_eq!(find("(a|)*", "aa").as_deref(), Some("aa"));
+        assert_eq!(find("()+x", "x").as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn long_lines_and_pathological_patterns() {
+        let long = "x".repeat(200_000);
+        assert_eq!(find(".*", &long).map(|m| m.len()), Some(200_000));
+        // Exponential for a backtracking engine
+        assert_eq!(find("(a|a)*b", &"a".repeat(28)), None);
+        assert_eq!(find("(a*)*c", &"a".repeat(5_000)), None);
+    }
+}
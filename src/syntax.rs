@@ -0,0 +1,218 @@
+use std::path::Path;
+
+/// Languages with built-in highlighting rules
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Language {
+    Rust,
+    Python,
+    Json,
+}
+
+/// Kind of text a character belongs to, used to pick its color
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Token {
+    Plain,
+    Keyword,
+    String,
+    Comment,
+    Number,
+}
+
+/// Tokenizer state carried from the end of one line to the start of the next
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LineState {
+    #[default]
+    Normal,
+    /// Inside a (possibly nested) block comment
+    BlockComment(u8),
+    /// Inside a string that continues past the end of the line
+    String { quote: char, triple: bool },
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+const JSON_KEYWORDS: &[&str] = &["true", "false", "null"];
+
+impl Language {
+    /// Pick the language from a file extension
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let ext = Path::new(filename).extension()?.to_str()?;
+        match ext.to_ascii_lowercase().as_str() {
+            "rs" => Some(Self::Rust),
+            "py" | "pyw" => Some(Self::Python),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    fn keywords(self) -> &'static [&'static str] {
+        match self {
+            Self::Rust => RUST_KEYWORDS,
+            Self::Python => PYTHON_KEYWORDS,
+            Self::Json => JSON_KEYWORDS,
+        }
+    }
+
+    fn line_comment(self) -> Option<&'static str> {
+        match self {
+            Self::Rust => Some("//"),
+            Self::Python => Some("#"),
+            Self::Json => None,
+        }
+    }
+}
+
+fn starts_with(chars: &[char], i: usize, pat: &str) -> bool {
+    (i..)
+        .zip(pat.chars())
+        .all(|(j, p)| chars.get(j) == Some(&p))
+}
+
+/// Tokenize one line starting in `state`.
+/// Returns a token per character and the state at the end of the line.
+pub fn highlight_line(lang: Language, chars: &[char], state: LineState) -> (Vec<Token>, LineState) {
+    let mut tokens = vec![Token::Plain; chars.len()];
+    let mut state = state;
+    let mut i = 0;
+
+    while i < chars.len() {
+        match state {
+            LineState::BlockComment(depth) => {
+                tokens[i] = Token::Comment;
+                if starts_with(chars, i, "*/") {
+                    tokens[i + 1] = Token::Comment;
+                    i += 2;
+                    state = if depth > 1 {
+                        LineState::BlockComment(depth - 1)
+                    } else {
+                        LineState::Normal
+                    };
+                    continue;
+                }
+                if starts_with(chars, i, "/*") {
+                    tokens[i + 1] = Token::Comment;
+                    i += 2;
+                    state = LineState::BlockComment(depth.saturating_add(1));
+                    continue;
+                }
+                i += 1;
+            }
+            LineState::String { quote, triple } => {
+                tokens[i] = Token::String;
+                if chars[i] == '\\' {
+                    if let Some(t) = tokens.get_mut(i + 1) {
+                        *t = Token::String;
+                    }
+                    i += 2;
+                    continue;
+                }
+                let closing: String = if triple {
+                    std::iter::repeat_n(quote, 3).collect()
+                } else {
+                    quote.to_string()
+                };
+                if starts_with(chars, i, &closing) {
+                    let end = i + closing.chars().count();
+                    tokens[i..end].fill(Token::String);
+                    i = end;
+                    state = LineState::Normal;
+                    continue;
+                }
+                i += 1;
+            }
+            LineState::Normal => {
+                let ch = chars[i];
+                if lang
+                    .line_comment()
+                    .is_some_and(|c| starts_with(chars, i, c))
+                {
+                    tokens[i..].fill(Token::Comment);
+                    break;
+                }
+                if lang == Language::Rust && starts_with(chars, i, "/*") {
+                    tokens[i] = Token::Comment;
+                    tokens[i + 1] = Token::Comment;
+                    i += 2;
+                    state = LineState::BlockComment(1);
+                    continue;
+                }
+                if ch == '"' || (ch == '\'' && lang == Language::Python) {
+                    let triple = lang == Language::Python
+                        && starts_with(chars, i, &std::iter::repeat_n(ch, 3).collect::<String>());
+                    let open = if triple { 3 } else { 1 };
+                    tokens[i..i + open].fill(Token::String);
+                    i += open;
+                    state = LineState::String { quote: ch, triple };
+                    continue;
+                }
+                if ch == '\'' && lang == Language::Rust {
+                    // Character literal such as 'a' or '\n'; anything else is a lifetime
+                    let len = if chars.get(i + 1) == Some(&'\\') {
+                        chars
+                            .get(i + 3..)
+                            .and_then(|rest| rest.iter().position(|&c| c == '\''))
+                            .map(|p| p + 4)
+                    } else {
+                        (chars.get(i + 2) == Some(&'\'')).then_some(3)
+                    };
+                    if let Some(len) = len.filter(|&l| i + l <= chars.len()) {
+                        tokens[i..i + len].fill(Token::String);
+                        i += len;
+                        continue;
+                    }
+                }
+                if ch.is_ascii_digit() && (i == 0 || !is_ident_char(chars[i - 1])) {
+                    let start = i;
+                    while i < chars.len()
+                        && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+                    {
+                        // Stop before a method call or range such as `1..2`
+                        if chars[i] == '.' && !chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())
+                        {
+                            break;
+                        }
+                        i += 1;
+                    }
+                    tokens[start..i].fill(Token::Number);
+                    continue;
+                }
+                if is_ident_char(ch) {
+                    let start = i;
+                    while i < chars.len() && is_ident_char(chars[i]) {
+                        i += 1;
+                    }
+                    let word: String = chars[start..i].iter().collect();
+                    if lang.keywords().contains(&word.as_str()) {
+                        tokens[start..i].fill(Token::Keyword);
+                    }
+                    continue;
+                }
+                i += 1;
+            }
+        }
+    }
+
+    // Only triple-quoted Python strings and Rust strings continue onto the next line
+    if let LineState::String { triple: false, .. } = state
+        && lang != Language::Rust
+    {
+        state = LineState::Normal;
+    }
+    (tokens, state)
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
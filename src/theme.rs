@@ -0,0 +1,203 @@
+use ratatui::style::Color;
+
+use rano_text_editor::config::{Config, Value};
+
+/// Colors used to draw the editor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Theme {
+    pub background: Color,
+    pub foreground: Color,
+    pub border: Color,
+    pub status_bg: Color,
+    pub status_fg: Color,
+    pub gutter: Color,
+    pub current_line: Color,
+    pub search_match: Color,
+    pub active_match: Color,
+    /// Text drawn on top of search matches and matching brackets
+    pub highlight_fg: Color,
+    pub bracket_match: Color,
+    pub bracket_unmatched: Color,
+    /// Spaces and tabs when visible whitespace is on
+    pub whitespace: Color,
+    /// Background of trailing spaces and tabs, and their color when whitespace is visible
+    pub trailing_whitespace: Color,
+    /// Mark names shown in the gutter
+    pub mark: Color,
+    /// Gutter bars beside lines added or modified since the file was opened or saved
+    pub added: Color,
+    pub modified: Color,
+    pub keyword: Color,
+    pub string: Color,
+    pub comment: Color,
+    pub number: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            background: Color::Reset,
+            foreground: Color::White,
+            border: Color::Cyan,
+            status_bg: Color::DarkGray,
+            status_fg: Color::White,
+            gutter: Color::DarkGray,
+            current_line: Color::Rgb(40, 44, 52),
+            search_match: Color::Yellow,
+            active_match: Color::LightRed,
+            highlight_fg: Color::Black,
+            bracket_match: Color::Cyan,
+            bracket_unmatched: Color::Red,
+            whitespace: Color::DarkGray,
+            trailing_whitespace: Color::LightRed,
+            mark: Color::Yellow,
+            added: Color::Green,
+            modified: Color::Yellow,
+            keyword: Color::Magenta,
+            string: Color::Green,
+            comment: Color::DarkGray,
+            number: Color::Cyan,
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            background: Color::White,
+            foreground: Color::Black,
+            border: Color::Blue,
+            status_bg: Color::Gray,
+            status_fg: Color::Black,
+            gutter: Color::Gray,
+            current_line: Color::Rgb(234, 234, 242),
+            search_match: Color::LightYellow,
+            active_match: Color::LightRed,
+            highlight_fg: Color::Black,
+            bracket_match: Color::LightBlue,
+            bracket_unmatched: Color::Red,
+            whitespace: Color::Gray,
+            trailing_whitespace: Color::Red,
+            mark: Color::Magenta,
+            added: Color::Green,
+            modified: Color::Rgb(181, 137, 0),
+            keyword: Color::Magenta,
+            string: Color::Green,
+            comment: Color::Gray,
+            number: Color::Blue,
+        }
+    }
+
+    /// Built-in theme called `name`
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            _ => None,
+        }
+    }
+
+    /// Build the theme from the `[theme]` section of `config`.
+    /// `name` (from `--theme`) overrides the section's `name` entry for the base theme;
+    /// individual colors set in the section are then applied on top. Unknown names and
+    /// invalid colors are reported and the base theme's colors are kept.
+    pub fn from_config(config: &Config, name: Option<&str>) -> (Self, Vec<String>) {
+        let mut errors = Vec::new();
+        let configured_name = config.section("theme").find_map(|e| match &e.value {
+            Value::Str(s) if e.key == "name" => Some(s.clone()),
+            _ => None,
+        });
+        let mut theme = match name.map(str::to_string).or(configured_name) {
+            Some(name) => Self::builtin(&name).unwrap_or_else(|| {
+                errors.push(format!("unknown theme `{}`", name));
+                Self::default()
+            }),
+            None => Self::default(),
+        };
+
+        for entry in config.section("theme").filter(|e| e.key != "name") {
+            let Some(slot) = theme.color_mut(&entry.key) else {
+                errors.push(format!(
+                    "line {}: unknown color `{}`",
+                    entry.line, entry.key
+                ));
+                continue;
+            };
+            match &entry.value {
+                Value::Str(s) => match parse_color(s) {
+                    Some(color) => *slot = color,
+                    None => errors.push(format!("line {}: invalid color `{}`", entry.line, s)),
+                },
+                _ => errors.push(format!("line {}: colors must be strings", entry.line)),
+            }
+        }
+        (theme, errors)
+    }
+
+    fn color_mut(&mut self, key: &str) -> Option<&mut Color> {
+        Some(match key {
+            "background" => &mut self.background,
+            "foreground" => &mut self.foreground,
+            "border" => &mut self.border,
+            "status_bg" => &mut self.status_bg,
+            "status_fg" => &mut self.status_fg,
+            "gutter" => &mut self.gutter,
+            "current_line" => &mut self.current_line,
+            "search_match" => &mut self.search_match,
+            "active_match" => &mut self.active_match,
+            "highlight_fg" => &mut self.highlight_fg,
+            "bracket_match" => &mut self.bracket_match,
+            "bracket_unmatched" => &mut self.bracket_unmatched,
+            "whitespace" => &mut self.whitespace,
+            "trailing_whitespace" => &mut self.trailing_whitespace,
+            "mark" => &mut self.mark,
+            "added" => &mut self.added,
+            "modified" => &mut self.modified,
+            "keyword" => &mut self.keyword,
+            "string" => &mut self.string,
+            "comment" => &mut self.comment,
+            "number" => &mut self.number,
+            _ => return None,
+        })
+    }
+}
+
+/// Parse a color name such as `lightblue` or `dark_gray`, or a hex value like `#1e1e2e`
+pub fn parse_color(text: &str) -> Option<Color> {
+    if let Some(hex) = text.strip_prefix('#') {
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+    let name: String = text
+        .chars()
+        .filter(|c| !matches!(c, '_' | '-' | ' '))
+        .collect::<String>()
+        .to_ascii_lowercase();
+    Some(match name.as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    })
+}